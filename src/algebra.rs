@@ -1,113 +1,266 @@
-use std::{cmp::Ordering, cmp::Eq, collections::HashMap};
+use std::{cmp::Ordering, cmp::Eq, collections::HashMap, collections::VecDeque};
 use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::{Add, Sub, Neg, Mul, Index, IndexMut};
+use std::fmt::{self};
+use num::{Zero, One, BigInt, Integer, ToPrimitive, CheckedAdd};
 
-#[allow(unused)]
-#[derive(Debug, Eq, Ord, PartialEq, PartialOrd, Clone, Hash, Copy)]
-pub struct Position2D<T> {
-    pub x : T,
-    pub y : T
+pub trait CoordinateOrder {
+    fn compare<T : Ord>(a : &[T], b : &[T]) -> Ordering;
 }
 
-#[allow(unused)]
-impl<T> Position2D<T> {
-    pub fn new(x : T, y : T) -> Position2D<T> {
-        Position2D { x, y }
+// compare x first, then y, then z, ...
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Copy)]
+pub struct ColumnMajor;
+
+// compare the last coordinate first (y before x in 2D), i.e. reading order
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Copy)]
+pub struct RowMajor;
+
+impl CoordinateOrder for ColumnMajor {
+    fn compare<T : Ord>(a : &[T], b : &[T]) -> Ordering {
+        a.iter().cmp(b.iter())
     }
 }
 
+impl CoordinateOrder for RowMajor {
+    fn compare<T : Ord>(a : &[T], b : &[T]) -> Ordering {
+        a.iter().rev().cmp(b.iter().rev())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Copy)]
+pub struct Point<T, const N : usize, O = ColumnMajor> {
+    pub coords : [T; N],
+    order : PhantomData<O>
+}
+
+#[allow(unused)]
+pub type Position2D<T> = Point<T, 2>;
+#[allow(unused)]
+pub type Point2D = Point<i32, 2>;
+#[allow(unused)]
+pub type Point2DAlt = Point<i32, 2, RowMajor>;
+#[allow(unused)]
+pub type Point3D = Point<i32, 3>;
+
 #[allow(unused)]
-#[derive(Debug, Eq, Clone, Hash, Copy)]
-pub struct Point2D {
-    pub x : i32,
-    pub y : i32
+impl<T, const N : usize, O> Point<T, N, O> {
+    pub fn from_coords(coords : [T; N]) -> Point<T, N, O> {
+        Point { coords, order : PhantomData }
+    }
 }
 
 #[allow(unused)]
-impl Point2D {
-    pub fn new(x : i32, y : i32) -> Point2D {
-        Point2D { x, y }
+impl<T : Copy, O> Point<T, 2, O> {
+    pub fn new(x : T, y : T) -> Point<T, 2, O> {
+        Point::from_coords([x, y])
+    }
+
+    pub fn x(&self) -> T {
+        self.coords[0]
+    }
+
+    pub fn y(&self) -> T {
+        self.coords[1]
+    }
+
+    pub fn x_mut(&mut self) -> &mut T {
+        &mut self.coords[0]
+    }
+
+    pub fn y_mut(&mut self) -> &mut T {
+        &mut self.coords[1]
     }
 }
 
-impl PartialOrd for Point2D {
-    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+#[allow(unused)]
+impl<T : Copy, O> Point<T, 3, O> {
+    pub fn new(x : T, y : T, z : T) -> Point<T, 3, O> {
+        Point::from_coords([x, y, z])
+    }
+
+    pub fn x(&self) -> T {
+        self.coords[0]
+    }
+
+    pub fn y(&self) -> T {
+        self.coords[1]
+    }
+
+    pub fn z(&self) -> T {
+        self.coords[2]
+    }
+
+    pub fn x_mut(&mut self) -> &mut T {
+        &mut self.coords[0]
+    }
+
+    pub fn y_mut(&mut self) -> &mut T {
+        &mut self.coords[1]
+    }
+
+    pub fn z_mut(&mut self) -> &mut T {
+        &mut self.coords[2]
     }
 }
 
-impl Ord for Point2D {
-    fn cmp(&self, other: &Self) -> Ordering {
-        if self.x < other.x {
-            Ordering::Less
+#[allow(unused)]
+impl<T, const N : usize, O> Point<T, N, O>
+where T : Copy + Zero + One + PartialOrd + Add<Output = T> + Sub<Output = T> {
+    pub fn origin() -> Point<T, N, O> {
+        Point::from_coords([T::zero(); N])
+    }
+
+    pub fn manhattan(&self, other : &Self) -> T {
+        let mut dist = T::zero();
+        for i in 0..N {
+            dist = dist + abs_diff(self.coords[i], other.coords[i]);
         }
-        else if self.x == other.x {
-            if self.y < other.y {
-                Ordering::Less
+        dist
+    }
+
+    pub fn chebyshev(&self, other : &Self) -> T {
+        let mut dist = T::zero();
+        for i in 0..N {
+            let d = abs_diff(self.coords[i], other.coords[i]);
+            if d > dist {
+                dist = d;
             }
-            else if self.y == other.y {
-                Ordering::Equal
+        }
+        dist
+    }
+
+    // the 2*N points at distance 1 along a single axis
+    pub fn orthogonal_neighbors(&self) -> Vec<Point<T, N, O>> {
+        let mut neighbors = Vec::with_capacity(2 * N);
+        for i in 0..N {
+            let mut before = self.coords;
+            before[i] = before[i] - T::one();
+            neighbors.push(Point::from_coords(before));
+
+            let mut after = self.coords;
+            after[i] = after[i] + T::one();
+            neighbors.push(Point::from_coords(after));
+        }
+        neighbors
+    }
+
+    // all the 3^N - 1 points with a Chebyshev distance of 1, diagonals included
+    pub fn neighbors(&self) -> Vec<Point<T, N, O>> {
+        let mut neighbors = Vec::new();
+        let mut offsets = [0u8; N];
+
+        loop {
+            // offsets are digits in base 3: 0 => -1, 1 => 0, 2 => +1
+            if offsets.iter().any(|&o| o != 1) {
+                let mut coords = self.coords;
+                for i in 0..N {
+                    match offsets[i] {
+                        0 => coords[i] = coords[i] - T::one(),
+                        2 => coords[i] = coords[i] + T::one(),
+                        _ => {}
+                    }
+                }
+                neighbors.push(Point::from_coords(coords));
             }
-            else {
-                Ordering::Greater
+
+            let mut i = 0;
+            while i < N && offsets[i] == 2 {
+                offsets[i] = 0;
+                i += 1;
             }
+            if i == N {
+                break;
+            }
+            offsets[i] += 1;
         }
-        else {
-            Ordering::Greater
-        }
+
+        neighbors
     }
 }
 
-impl PartialEq for Point2D {
-    fn eq(&self, other: &Self) -> bool {
-        self.x == other.x && self.y == other.y
+fn abs_diff<T>(a : T, b : T) -> T
+where T : PartialOrd + Sub<Output = T> {
+    if a > b { a - b } else { b - a }
+}
+
+impl<T : Ord, const N : usize, O : CoordinateOrder + Eq> PartialOrd for Point<T, N, O> {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-#[allow(unused)]
-#[derive(Debug, Eq, Clone, Hash, Copy)]
-pub struct Point2DAlt {
-    pub x : i32,
-    pub y : i32
+impl<T : Ord, const N : usize, O : CoordinateOrder + Eq> Ord for Point<T, N, O> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        O::compare(&self.coords, &other.coords)
+    }
 }
 
-impl Point2DAlt {
-    #[allow(unused)]
-    pub fn new(x : i32, y : i32) -> Point2DAlt {
-        Point2DAlt { x, y }
+impl<T, const N : usize, O> Index<usize> for Point<T, N, O> {
+    type Output = T;
+
+    fn index(&self, i : usize) -> &T {
+        &self.coords[i]
     }
 }
 
-impl PartialOrd for Point2DAlt {
-    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl<T, const N : usize, O> IndexMut<usize> for Point<T, N, O> {
+    fn index_mut(&mut self, i : usize) -> &mut T {
+        &mut self.coords[i]
     }
 }
 
-impl Ord for Point2DAlt {
-    fn cmp(&self, other: &Self) -> Ordering {
-        if self.y < other.y {
-            Ordering::Less
-        }
-        else if self.y == other.y {
-            if self.x < other.x {
-                Ordering::Less
-            }
-            else if self.x == other.x {
-                Ordering::Equal
-            }
-            else {
-                Ordering::Greater
-            }
+impl<T : Copy + Add<Output = T>, const N : usize, O> Add for Point<T, N, O> {
+    type Output = Point<T, N, O>;
+
+    fn add(self, other : Self) -> Self::Output {
+        let mut coords = self.coords;
+        for (c, o) in coords.iter_mut().zip(other.coords) {
+            *c = *c + o;
         }
-        else {
-            Ordering::Greater
+        Point::from_coords(coords)
+    }
+}
+
+impl<T : Copy + Sub<Output = T>, const N : usize, O> Sub for Point<T, N, O> {
+    type Output = Point<T, N, O>;
+
+    fn sub(self, other : Self) -> Self::Output {
+        let mut coords = self.coords;
+        for (c, o) in coords.iter_mut().zip(other.coords) {
+            *c = *c - o;
         }
+        Point::from_coords(coords)
+    }
+}
+
+impl<T : Copy + Neg<Output = T>, const N : usize, O> Neg for Point<T, N, O> {
+    type Output = Point<T, N, O>;
+
+    fn neg(self) -> Self::Output {
+        Point::from_coords(self.coords.map(|c| -c))
     }
 }
 
-impl PartialEq for Point2DAlt {
-    fn eq(&self, other: &Self) -> bool {
-        self.x == other.x && self.y == other.y
+impl<T : Copy + Mul<Output = T>, const N : usize, O> Mul<T> for Point<T, N, O> {
+    type Output = Point<T, N, O>;
+
+    fn mul(self, scalar : T) -> Self::Output {
+        Point::from_coords(self.coords.map(|c| c * scalar))
+    }
+}
+
+impl<T : fmt::Display, const N : usize, O> fmt::Display for Point<T, N, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, c) in self.coords.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        write!(f, ")")
     }
 }
 
@@ -261,34 +414,715 @@ where T : Copy{
     }
 }
 
+// dense voxel grid; the voxel at `origin` is stored at index 0, so coordinates may be negative
 #[allow(unused)]
+#[derive(Clone)]
 pub struct Matrix3D<T> {
+    pub origin : Point3D,
     pub size_x : usize,
     pub size_y : usize,
     pub size_z : usize,
-    pub data : Vec<Vec<Vec<T>>>
+    pub data : Vec<T>
 }
 
 #[allow(unused)]
 impl<T> Matrix3D<T> 
-where T: Clone + Copy{
+where T: Clone + Copy + PartialEq {
     pub fn new(sx : usize, sy : usize, sz : usize, value : T) -> Matrix3D<T> {
-        Matrix3D { size_x: sx, size_y: sy, size_z: sz, data: vec![vec![vec![value; sz]; sy]; sz] }
+        Matrix3D::new_with_origin(Point3D::origin(), sx, sy, sz, value)
+    }
+
+    pub fn new_with_origin(origin : Point3D, sx : usize, sy : usize, sz : usize, value : T) -> Matrix3D<T> {
+        Matrix3D { origin, size_x: sx, size_y: sy, size_z: sz, data: vec![value; sx * sy * sz] }
     }
 
-    pub fn element_at(&self, x : usize, y : usize, z : usize) -> Option<T> {
-        if x < self.size_x && y < self.size_y && z < self.size_z {
-            return Some(self.data[x][y][z]);
+    // the grid covering all the voxels between min and max, inclusive
+    pub fn from_bounds(min : Point3D, max : Point3D, value : T) -> Matrix3D<T> {
+        let size = max - min + Point3D::new(1, 1, 1);
+        Matrix3D::new_with_origin(
+            min,
+            usize::try_from(size.x()).unwrap(),
+            usize::try_from(size.y()).unwrap(),
+            usize::try_from(size.z()).unwrap(),
+            value)
+    }
+
+    // the voxel with the largest coordinates in the grid
+    pub fn max_corner(&self) -> Point3D {
+        self.origin + Point3D::new(
+            i32::try_from(self.size_x).unwrap() - 1,
+            i32::try_from(self.size_y).unwrap() - 1,
+            i32::try_from(self.size_z).unwrap() - 1)
+    }
+
+    fn index_of(&self, x : i32, y : i32, z : i32) -> Option<usize> {
+        let ux = usize::try_from(x - self.origin.x()).ok()?;
+        let uy = usize::try_from(y - self.origin.y()).ok()?;
+        let uz = usize::try_from(z - self.origin.z()).ok()?;
+
+        if ux < self.size_x && uy < self.size_y && uz < self.size_z {
+            return Some((ux * self.size_y + uy) * self.size_z + uz);
         }
-        println!("{},{},{}", x,y,z);
         None
     }
 
-    pub fn set_at(&mut self, x : usize, y : usize, z : usize, value : T) -> bool {
-        if x < self.size_x && y < self.size_y && z < self.size_z {
-            self.data[x][y][z] = value;
+    fn point_of(&self, index : usize) -> Point3D {
+        let uz = index % self.size_z;
+        let uy = (index / self.size_z) % self.size_y;
+        let ux = index / (self.size_z * self.size_y);
+
+        self.origin + Point3D::new(
+            i32::try_from(ux).unwrap(),
+            i32::try_from(uy).unwrap(),
+            i32::try_from(uz).unwrap())
+    }
+
+    pub fn contains(&self, p : &Point3D) -> bool {
+        self.index_of(p.x(), p.y(), p.z()).is_some()
+    }
+
+    pub fn element_at(&self, x : i32, y : i32, z : i32) -> Option<T> {
+        self.index_of(x, y, z).map(|i| self.data[i])
+    }
+
+    pub fn element_at_point(&self, p : &Point3D) -> Option<T> {
+        self.element_at(p.x(), p.y(), p.z())
+    }
+
+    pub fn set_at(&mut self, x : i32, y : i32, z : i32, value : T) -> bool {
+        if let Some(i) = self.index_of(x, y, z) {
+            self.data[i] = value;
             return true;
         }
         false
     }
-}
\ No newline at end of file
+
+    pub fn set_at_point(&mut self, p : &Point3D, value : T) -> bool {
+        self.set_at(p.x(), p.y(), p.z(), value)
+    }
+
+    // all the voxels in the grid, in storage order, with their values
+    pub fn voxels(&self) -> impl Iterator<Item = (Point3D, T)> + '_ {
+        self.data.iter().enumerate().map(|(i, v)| (self.point_of(i), *v))
+    }
+
+    // the face-adjacent voxels of p that are inside the grid
+    pub fn neighbors6(&self, p : &Point3D) -> Vec<Point3D> {
+        p.orthogonal_neighbors().into_iter().filter(|n| self.contains(n)).collect()
+    }
+
+    // replace the face-connected region of voxels that have the same value as the seed with value;
+    // returns the voxels that were changed, in BFS order
+    pub fn flood_fill(&mut self, seed : &Point3D, value : T) -> Vec<Point3D> {
+        let mut filled = Vec::new();
+
+        let target = match self.element_at_point(seed) {
+            Some(v) if v != value => v,
+            _ => return filled
+        };
+
+        let mut queue = VecDeque::new();
+        self.set_at_point(seed, value);
+        queue.push_back(*seed);
+
+        while let Some(p) = queue.pop_front() {
+            for n in self.neighbors6(&p) {
+                if self.element_at_point(&n) == Some(target) {
+                    self.set_at_point(&n, value);
+                    queue.push_back(n);
+                }
+            }
+            filled.push(p);
+        }
+
+        filled
+    }
+
+    // the face-connected components made of voxels with the given value;
+    // components are ordered by their first voxel in storage order
+    pub fn connected_components(&self, value : T) -> Vec<Vec<Point3D>> {
+        let mut components = Vec::new();
+        let mut visited = vec![false; self.data.len()];
+
+        for start in 0..self.data.len() {
+            if visited[start] || self.data[start] != value {
+                continue;
+            }
+
+            let mut component = Vec::new();
+            let mut queue = VecDeque::new();
+            visited[start] = true;
+            queue.push_back(self.point_of(start));
+
+            while let Some(p) = queue.pop_front() {
+                for n in self.neighbors6(&p) {
+                    let i = self.index_of(n.x(), n.y(), n.z()).unwrap();
+                    if !visited[i] && self.data[i] == value {
+                        visited[i] = true;
+                        queue.push_back(n);
+                    }
+                }
+                component.push(p);
+            }
+
+            components.push(component);
+        }
+
+        components
+    }
+}
+
+// Manhattan geometry in coordinates rotated by 45 degrees: u = x + y, v = x - y.
+// A Manhattan ball becomes an axis-aligned square and lattice points are the (u, v) with u and v of the same parity.
+
+#[allow(unused)]
+pub fn to_rotated(p : &Point2D) -> (i32, i32) {
+    (p.x() + p.y(), p.x() - p.y())
+}
+
+#[allow(unused)]
+pub fn from_rotated(u : i32, v : i32) -> Option<Point2D> {
+    if (u - v).rem_euclid(2) == 0 {
+        return Some(Point2D::new((u + v) / 2, (u - v) / 2));
+    }
+    None
+}
+
+// the inclusive rectangle [u_min, u_max] x [v_min, v_max] in rotated coordinates
+#[allow(unused)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct RotatedRect {
+    pub u_min : i32,
+    pub u_max : i32,
+    pub v_min : i32,
+    pub v_max : i32
+}
+
+#[allow(unused)]
+impl RotatedRect {
+    pub fn contains(&self, p : &Point2D) -> bool {
+        let (u, v) = to_rotated(p);
+        self.u_min <= u && u <= self.u_max && self.v_min <= v && v <= self.v_max
+    }
+
+    pub fn intersection(&self, other : &RotatedRect) -> Option<RotatedRect> {
+        let rect = RotatedRect {
+            u_min : self.u_min.max(other.u_min),
+            u_max : self.u_max.min(other.u_max),
+            v_min : self.v_min.max(other.v_min),
+            v_max : self.v_max.min(other.v_max)
+        };
+
+        if rect.u_min <= rect.u_max && rect.v_min <= rect.v_max {
+            return Some(rect);
+        }
+        None
+    }
+
+    // the lattice points inside the rectangle that are also inside the box [min, max]
+    pub fn points_within(&self, min : &Point2D, max : &Point2D) -> Vec<Point2D> {
+        let mut points = Vec::new();
+
+        // x = (u + v) / 2 and y = (u - v) / 2 must stay inside the box, which bounds u on its own...
+        let u_lo = self.u_min.max(min.x() + min.y()).max(self.v_min + 2 * min.y()).max(2 * min.x() - self.v_max);
+        let u_hi = self.u_max.min(max.x() + max.y()).min(self.v_max + 2 * max.y()).min(2 * max.x() - self.v_min);
+
+        for u in u_lo..=u_hi {
+            // ...and v for every u
+            let mut v_lo = self.v_min.max(2 * min.x() - u).max(u - 2 * max.y());
+            let v_hi = self.v_max.min(2 * max.x() - u).min(u - 2 * min.y());
+
+            if (u - v_lo).rem_euclid(2) != 0 {
+                v_lo += 1;
+            }
+
+            for v in (v_lo..=v_hi).step_by(2) {
+                points.push(from_rotated(u, v).unwrap());
+            }
+        }
+
+        points
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum RotatedAxis {
+    U,
+    V
+}
+
+// a diagonal segment along which `axis` is constant (`value`) and the other rotated coordinate goes from `from` to `to`
+#[allow(unused)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct DiagonalSegment {
+    pub axis : RotatedAxis,
+    pub value : i32,
+    pub from : i32,
+    pub to : i32
+}
+
+#[allow(unused)]
+impl DiagonalSegment {
+    // the lattice point where two crossing segments meet, if any
+    pub fn intersection(&self, other : &DiagonalSegment) -> Option<Point2D> {
+        let (u_seg, v_seg) = match (self.axis, other.axis) {
+            (RotatedAxis::U, RotatedAxis::V) => (self, other),
+            (RotatedAxis::V, RotatedAxis::U) => (other, self),
+            _ => return None
+        };
+
+        let u = u_seg.value;
+        let v = v_seg.value;
+        if u_seg.from <= v && v <= u_seg.to && v_seg.from <= u && u <= v_seg.to {
+            return from_rotated(u, v);
+        }
+        None
+    }
+}
+
+// all the points within Manhattan distance `radius` from `center`
+#[allow(unused)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct Diamond {
+    pub center : Point2D,
+    pub radius : i32
+}
+
+#[allow(unused)]
+impl Diamond {
+    pub fn new(center : Point2D, radius : i32) -> Diamond {
+        Diamond { center, radius }
+    }
+
+    // the smallest diamond around center that reaches the given point
+    pub fn reaching(center : Point2D, edge : &Point2D) -> Diamond {
+        Diamond { center, radius : center.manhattan(edge) }
+    }
+
+    pub fn contains(&self, p : &Point2D) -> bool {
+        self.center.manhattan(p) <= self.radius
+    }
+
+    // the number of lattice points in the diamond
+    pub fn area(&self) -> i64 {
+        let r = self.radius as i64;
+        2 * r * r + 2 * r + 1
+    }
+
+    pub fn rotated(&self) -> RotatedRect {
+        let (u, v) = to_rotated(&self.center);
+        RotatedRect {
+            u_min : u - self.radius,
+            u_max : u + self.radius,
+            v_min : v - self.radius,
+            v_max : v + self.radius
+        }
+    }
+
+    pub fn contains_diamond(&self, other : &Diamond) -> bool {
+        self.center.manhattan(&other.center) + other.radius <= self.radius
+    }
+
+    // the intersection of two diamonds is a rectangle in rotated coordinates
+    pub fn intersection(&self, other : &Diamond) -> Option<RotatedRect> {
+        self.rotated().intersection(&other.rotated())
+    }
+
+    // the four edges, made of the points at distance exactly `radius` from the center
+    pub fn boundary(&self) -> [DiagonalSegment; 4] {
+        let rect = self.rotated();
+        [
+            DiagonalSegment { axis : RotatedAxis::U, value : rect.u_min, from : rect.v_min, to : rect.v_max },
+            DiagonalSegment { axis : RotatedAxis::U, value : rect.u_max, from : rect.v_min, to : rect.v_max },
+            DiagonalSegment { axis : RotatedAxis::V, value : rect.v_min, from : rect.u_min, to : rect.u_max },
+            DiagonalSegment { axis : RotatedAxis::V, value : rect.v_max, from : rect.u_min, to : rect.u_max },
+        ]
+    }
+
+    // the inclusive range of x covered on row y
+    pub fn row_span(&self, y : i32) -> Option<(i32, i32)> {
+        let half_width = self.radius - (y - self.center.y()).abs();
+        if half_width >= 0 {
+            return Some((self.center.x() - half_width, self.center.x() + half_width));
+        }
+        None
+    }
+}
+
+// split the rotated plane into the cells delimited by the edges of the diamonds (and of the extra bounds);
+// every cell is either inside or outside of each diamond
+fn rotated_cells(diamonds : &[Diamond], bounds : &RotatedRect) -> Vec<RotatedRect> {
+    let rects : Vec<RotatedRect> = diamonds.iter().map(|d| d.rotated()).collect();
+
+    let mut us = vec![bounds.u_min, bounds.u_max + 1];
+    let mut vs = vec![bounds.v_min, bounds.v_max + 1];
+    for r in &rects {
+        us.extend([r.u_min, r.u_max + 1]);
+        vs.extend([r.v_min, r.v_max + 1]);
+    }
+    us.sort();
+    us.dedup();
+    vs.sort();
+    vs.dedup();
+
+    let mut cells = Vec::new();
+    for i in 0..us.len() - 1 {
+        for j in 0..vs.len() - 1 {
+            let cell = RotatedRect { u_min : us[i], u_max : us[i + 1] - 1, v_min : vs[j], v_max : vs[j + 1] - 1 };
+            if cell.intersection(bounds).is_some() {
+                cells.push(cell);
+            }
+        }
+    }
+
+    cells
+}
+
+fn is_covered(cell : &RotatedRect, diamonds : &[Diamond]) -> bool {
+    diamonds.iter().any(|d| d.rotated().intersection(cell) == Some(*cell))
+}
+
+// the number of lattice points covered by at least one of the diamonds
+#[allow(unused)]
+pub fn diamond_union_area(diamonds : &[Diamond]) -> i64 {
+    if diamonds.is_empty() {
+        return 0;
+    }
+
+    let rects : Vec<RotatedRect> = diamonds.iter().map(|d| d.rotated()).collect();
+    let bounds = RotatedRect {
+        u_min : rects.iter().map(|r| r.u_min).min().unwrap(),
+        u_max : rects.iter().map(|r| r.u_max).max().unwrap(),
+        v_min : rects.iter().map(|r| r.v_min).min().unwrap(),
+        v_max : rects.iter().map(|r| r.v_max).max().unwrap()
+    };
+
+    rotated_cells(diamonds, &bounds).iter()
+        .filter(|cell| is_covered(cell, diamonds))
+        .map(lattice_points_in)
+        .sum()
+}
+
+// the number of (u, v) with u and v of the same parity inside the rectangle
+fn lattice_points_in(rect : &RotatedRect) -> i64 {
+    let count_parity = |lo : i32, hi : i32, parity : i32| -> i64 {
+        // the number of values in [lo, hi] congruent to parity modulo 2
+        let first = lo + (parity - lo).rem_euclid(2);
+        if first > hi { 0 } else { ((hi - first) / 2 + 1) as i64 }
+    };
+
+    count_parity(rect.u_min, rect.u_max, 0) * count_parity(rect.v_min, rect.v_max, 0) +
+    count_parity(rect.u_min, rect.u_max, 1) * count_parity(rect.v_min, rect.v_max, 1)
+}
+
+// every point of the box [min, max] that is not covered by any of the diamonds
+#[allow(unused)]
+pub fn uncovered_points(diamonds : &[Diamond], min : &Point2D, max : &Point2D) -> Vec<Point2D> {
+    let bounds = RotatedRect {
+        u_min : min.x() + min.y(),
+        u_max : max.x() + max.y(),
+        v_min : min.x() - max.y(),
+        v_max : max.x() - min.y()
+    };
+
+    let mut points : Vec<Point2D> = rotated_cells(diamonds, &bounds).iter()
+        .filter(|cell| !is_covered(cell, diamonds))
+        .flat_map(|cell| cell.points_within(min, max))
+        .collect();
+
+    points.sort_by_key(|p| (p.y(), p.x()));
+    points
+}
+
+// a set of integers stored as sorted, disjoint and non-adjacent closed intervals [lo, hi]
+#[allow(unused)]
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Default)]
+pub struct IntervalSet<T> {
+    intervals : Vec<(T, T)>
+}
+
+#[allow(unused)]
+impl<T> IntervalSet<T>
+where T : Copy + Ord + One + Zero + Add<Output = T> + Sub<Output = T> + CheckedAdd {
+    pub fn new() -> IntervalSet<T> {
+        IntervalSet { intervals : Vec::new() }
+    }
+
+    pub fn from_interval(lo : T, hi : T) -> IntervalSet<T> {
+        let mut set = IntervalSet::new();
+        set.insert(lo, hi);
+        set
+    }
+
+    pub fn from_intervals<I>(intervals : I) -> IntervalSet<T>
+    where I : IntoIterator<Item = (T, T)> {
+        let mut set = IntervalSet { intervals : intervals.into_iter().filter(|(lo, hi)| lo <= hi).collect() };
+        set.normalize();
+        set
+    }
+
+    pub fn intervals(&self) -> &[(T, T)] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    // sort the intervals and merge the ones that overlap or touch;
+    // an interval ending at the largest value touches everything after it
+    fn normalize(&mut self) {
+        self.intervals.sort();
+
+        let mut merged : Vec<(T, T)> = Vec::with_capacity(self.intervals.len());
+        for &(lo, hi) in &self.intervals {
+            match merged.last_mut() {
+                Some(last) if last.1.checked_add(&T::one()).is_none_or(|next| lo <= next) => last.1 = last.1.max(hi),
+                _ => merged.push((lo, hi))
+            }
+        }
+
+        self.intervals = merged;
+    }
+
+    pub fn insert(&mut self, lo : T, hi : T) {
+        if lo <= hi {
+            self.intervals.push((lo, hi));
+            self.normalize();
+        }
+    }
+
+    pub fn union(&self, other : &IntervalSet<T>) -> IntervalSet<T> {
+        IntervalSet::from_intervals(self.intervals.iter().chain(other.intervals.iter()).copied())
+    }
+
+    pub fn intersection(&self, other : &IntervalSet<T>) -> IntervalSet<T> {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a_lo, a_hi) = self.intervals[i];
+            let (b_lo, b_hi) = other.intervals[j];
+
+            let lo = a_lo.max(b_lo);
+            let hi = a_hi.min(b_hi);
+            if lo <= hi {
+                result.push((lo, hi));
+            }
+
+            if a_hi < b_hi {
+                i += 1;
+            }
+            else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { intervals : result }
+    }
+
+    pub fn difference(&self, other : &IntervalSet<T>) -> IntervalSet<T> {
+        let mut result = Vec::new();
+        let mut j = 0;
+
+        for &(lo, hi) in &self.intervals {
+            // the first value not removed yet, None once the whole interval is removed
+            let mut start = Some(lo);
+
+            // skip the intervals that end before this one
+            while j < other.intervals.len() && other.intervals[j].1 < lo {
+                j += 1;
+            }
+
+            let mut k = j;
+            while let Some(s) = start {
+                if k == other.intervals.len() || other.intervals[k].0 > hi {
+                    break;
+                }
+
+                let (b_lo, b_hi) = other.intervals[k];
+                if s < b_lo {
+                    result.push((s, b_lo - T::one()));
+                }
+                // b_hi < hi, so b_hi + 1 does not overflow
+                start = if b_hi >= hi { None } else { Some(s.max(b_hi + T::one())) };
+                k += 1;
+            }
+
+            if let Some(s) = start {
+                result.push((s, hi));
+            }
+        }
+
+        IntervalSet { intervals : result }
+    }
+
+    pub fn contains(&self, value : T) -> bool {
+        let i = self.intervals.partition_point(|&(_, hi)| hi < value);
+        i < self.intervals.len() && self.intervals[i].0 <= value
+    }
+
+    // true if every value of the other set is also in this one
+    pub fn covers(&self, other : &IntervalSet<T>) -> bool {
+        other.difference(self).is_empty()
+    }
+
+    pub fn overlaps(&self, other : &IntervalSet<T>) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    // the number of values in the set, counted in a wider type so that no range can overflow it
+    pub fn len(&self) -> u64
+    where T : Into<i128> {
+        self.intervals.iter().fold(0u64, |total, &(lo, hi)| {
+            let count = u64::try_from(hi.into() - lo.into() + 1).unwrap_or(u64::MAX);
+            total.saturating_add(count)
+        })
+    }
+
+    // the ranges between consecutive intervals
+    pub fn gaps(&self) -> impl Iterator<Item = (T, T)> + '_ {
+        self.intervals.windows(2).map(|w| (w[0].1 + T::one(), w[1].0 - T::one()))
+    }
+
+    // the ranges of [lo, hi] that are not in the set
+    pub fn gaps_within(&self, lo : T, hi : T) -> IntervalSet<T> {
+        IntervalSet::from_interval(lo, hi).difference(self)
+    }
+}
+
+// a balanced numeral system: an odd base b whose digits go from -(b-1)/2 to (b-1)/2, written with the characters
+// of the alphabet from the lowest digit; digit lists are least significant first, without leading zeros
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalancedBase<'a> {
+    alphabet : &'a [char]
+}
+
+#[allow(unused)]
+pub const SNAFU : BalancedBase<'static> = BalancedBase::new(&['=', '-', '0', '1', '2']).unwrap();
+#[allow(unused)]
+pub const BALANCED_TERNARY : BalancedBase<'static> = BalancedBase::new(&['-', '0', '+']).unwrap();
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumeralError {
+    Empty,
+    // the position (from the left) and the character
+    InvalidDigit(usize, char)
+}
+
+#[allow(unused)]
+impl<'a> BalancedBase<'a> {
+    // None unless the alphabet has an odd number (at least 3) of distinct characters
+    pub const fn new(alphabet : &'a [char]) -> Option<BalancedBase<'a>> {
+        if alphabet.len() < 3 || alphabet.len().is_multiple_of(2) {
+            return None;
+        }
+
+        let mut i = 0;
+        while i < alphabet.len() {
+            let mut j = i + 1;
+            while j < alphabet.len() {
+                if alphabet[i] == alphabet[j] {
+                    return None;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+
+        Some(BalancedBase { alphabet })
+    }
+
+    pub fn base(&self) -> i64 {
+        self.alphabet.len() as i64
+    }
+
+    pub fn max_digit(&self) -> i64 {
+        self.base() / 2
+    }
+
+    pub fn digit_value(&self, c : char) -> Option<i64> {
+        self.alphabet.iter().position(|&a| a == c).map(|i| i as i64 - self.max_digit())
+    }
+
+    pub fn digit_char(&self, digit : i64) -> Option<char> {
+        usize::try_from(digit + self.max_digit()).ok().and_then(|i| self.alphabet.get(i).copied())
+    }
+
+    // split a value in its digit and the carry to the next one
+    fn split(&self, value : i64) -> (i64, i64) {
+        let (q, r) = (value.div_euclid(self.base()), value.rem_euclid(self.base()));
+        if r > self.max_digit() { (r - self.base(), q + 1) } else { (r, q) }
+    }
+
+    // bring digit values of any size back in range, carrying to the next digits
+    pub fn normalize(&self, values : &[i64]) -> Vec<i64> {
+        let mut digits = Vec::with_capacity(values.len() + 1);
+        let mut carry = 0;
+        let mut i = 0;
+
+        while i < values.len() || carry != 0 {
+            let (digit, next) = self.split(values.get(i).copied().unwrap_or(0) + carry);
+            digits.push(digit);
+            carry = next;
+            i += 1;
+        }
+
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        digits
+    }
+
+    pub fn parse_digits(&self, text : &str) -> Result<Vec<i64>, NumeralError> {
+        if text.is_empty() {
+            return Err(NumeralError::Empty);
+        }
+
+        let mut digits = text.chars().enumerate()
+            .map(|(pos, c)| self.digit_value(c).ok_or(NumeralError::InvalidDigit(pos, c)))
+            .collect::<Result<Vec<i64>, NumeralError>>()?;
+
+        digits.reverse();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Ok(digits)
+    }
+
+    pub fn format_digits(&self, digits : &[i64]) -> String {
+        if digits.is_empty() {
+            return self.digit_char(0).unwrap().to_string();
+        }
+        digits.iter().rev().map(|&d| self.digit_char(d).expect("Digit out of range!")).collect()
+    }
+
+    pub fn digits_of(&self, value : &BigInt) -> Vec<i64> {
+        let base = BigInt::from(self.base());
+        let mut digits = Vec::new();
+        let mut value = value.clone();
+
+        while !value.is_zero() {
+            let (q, r) = value.div_mod_floor(&base);
+            let (digit, carry) = self.split(r.to_i64().unwrap());
+            digits.push(digit);
+            value = q + carry;
+        }
+        digits
+    }
+
+    pub fn value_of(&self, digits : &[i64]) -> BigInt {
+        digits.iter().rev().fold(BigInt::zero(), |n, &d| n * self.base() + d)
+    }
+
+    pub fn encode(&self, value : &BigInt) -> String {
+        self.format_digits(&self.digits_of(value))
+    }
+
+    pub fn decode(&self, text : &str) -> Result<BigInt, NumeralError> {
+        Ok(self.value_of(&self.parse_digits(text)?))
+    }
+}
@@ -1,4 +1,5 @@
 use crate::utils;
+use crate::algebra::IntervalSet;
 use std::path::Path;
 
 use regex::Regex;
@@ -17,6 +18,14 @@ pub fn execute() {
    
    let count2 = get_overlapping_ranges("./data/input04.txt");
    println!("count={}", count2);
+
+   // intervals reaching the largest value
+   assert_eq!(1u64 << 32, IntervalSet::from_interval(i32::MIN, i32::MAX).len());
+   assert_eq!(&[(5, i32::MAX)], IntervalSet::from_intervals([(10, i32::MAX), (5, 9)]).intervals());
+   let top = IntervalSet::from_interval(0, i32::MAX);
+   assert_eq!(IntervalSet::from_interval(0, 4), top.difference(&IntervalSet::from_interval(5, i32::MAX)));
+   assert!(top.difference(&top).is_empty());
+   assert!(top.covers(&IntervalSet::from_interval(i32::MAX, i32::MAX)));
    
    println!();
 }
@@ -39,7 +48,10 @@ where P: AsRef<Path>, {
             let b2 = caps.get(3).unwrap().as_str().parse::<i32>().unwrap();
             let e2 = caps.get(4).unwrap().as_str().parse::<i32>().unwrap();
 
-            if (b1 <= b2 && e1 >= e2) || (b2 <= b1 && e2 >= e1) {
+            let r1 = IntervalSet::from_interval(b1, e1);
+            let r2 = IntervalSet::from_interval(b2, e2);
+
+            if r1.covers(&r2) || r2.covers(&r1) {
                count += 1;
             }
          }
@@ -67,8 +79,10 @@ where P: AsRef<Path>, {
             let b2 = caps.get(3).unwrap().as_str().parse::<i32>().unwrap();
             let e2 = caps.get(4).unwrap().as_str().parse::<i32>().unwrap();
 
-            if (b1 <= b2 && b2 <= e1) || (b1 <= e2 && e2 <= e1) ||
-               (b2 <= b1 && b1 <= e2) || (b2 <= e1 && e1 <= e2) {
+            let r1 = IntervalSet::from_interval(b1, e1);
+            let r2 = IntervalSet::from_interval(b2, e2);
+
+            if r1.overlaps(&r2) {
                count += 1;
             }
          }
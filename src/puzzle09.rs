@@ -1,31 +1,19 @@
 use crate::utils;
+use crate::algebra::Point2D;
 use std::collections::HashSet;
 use std::path::Path;
-use std::fmt::{self};
-
-// Define a struct to represent a point in 2D space
-#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
-struct Point {
-    x: i32,
-    y: i32,
-}
 
+type Point = Point2D;
 type PointSet = HashSet<Point>;
 
-impl fmt::Display for Point {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({},{})", self.x, self.y)
-    }
-}
-
 fn run_simulation<P>(filename : P, length: usize) -> PointSet
 where P : AsRef<Path> {
     let mut points = PointSet::new();
     let mut rope : Vec<Point> = Vec::new();
     for _ in 0..length {
-        rope.push(Point{x: 0, y: 0})
+        rope.push(Point::origin())
     }
-    points.insert(Point{x: 0, y: 0});
+    points.insert(Point::origin());
 
     if let Ok(lines) = utils::read_lines(filename) {
         for line in lines {
@@ -59,16 +47,16 @@ fn move_head(head : &Point, dir : char) -> Point {
 
     match dir {
         'U' => {
-            next_head.y -= 1;            
+            *next_head.y_mut() -= 1;            
         },
         'D' => {
-            next_head.y += 1;
+            *next_head.y_mut() += 1;
         },
         'R' => {
-            next_head.x += 1;
+            *next_head.x_mut() += 1;
         },
         'L' => {
-            next_head.x -= 1;
+            *next_head.x_mut() -= 1;
         },
         _ => panic!("Unexpected command '{}'!", dir)
     }
@@ -79,43 +67,43 @@ fn move_head(head : &Point, dir : char) -> Point {
 fn move_tail(next_head : &Point, tail : &Point, dir : char) -> Point {
     let mut next_tail = *tail;
 
-    if (next_head.x - next_tail.x).abs() > 1 ||
-       (next_head.y - next_tail.y).abs() > 1 {
+    if (next_head.x() - next_tail.x()).abs() > 1 ||
+       (next_head.y() - next_tail.y()).abs() > 1 {
         // same line
-        if next_head.y == next_tail.y {
+        if next_head.y() == next_tail.y() {
             match dir {
                 'R' => {
-                    next_tail.x += 1;
+                    *next_tail.x_mut() += 1;
                 },
                 'L' => {
-                    next_tail.x -= 1;
+                    *next_tail.x_mut() -= 1;
                 },
                 'U'|'D' => {
-                    if next_head.x < next_tail.x {
-                        next_tail.x -= 1;
+                    if next_head.x() < next_tail.x() {
+                        *next_tail.x_mut() -= 1;
                     }
-                    else if next_head.x > next_tail.x {
-                        next_tail.x += 1;
+                    else if next_head.x() > next_tail.x() {
+                        *next_tail.x_mut() += 1;
                     }
                 }                
                 _ => panic!("Unexpected command '{}'!", dir)
             }
         }
         // same column
-        else if next_head.x == next_tail.x {
+        else if next_head.x() == next_tail.x() {
             match dir {
                 'U' => {
-                    next_tail.y -= 1;            
+                    *next_tail.y_mut() -= 1;            
                 },
                 'D' => {
-                    next_tail.y += 1;
+                    *next_tail.y_mut() += 1;
                 },
                 'L'|'R' => {
-                    if next_head.y < next_tail.y {
-                        next_tail.y -= 1;
+                    if next_head.y() < next_tail.y() {
+                        *next_tail.y_mut() -= 1;
                     }
-                    else if next_head.y > next_tail.y {
-                        next_tail.y += 1;
+                    else if next_head.y() > next_tail.y() {
+                        *next_tail.y_mut() += 1;
                     }
                 }
                 _ => panic!("Unexpected command '{}'!", dir)
@@ -126,30 +114,30 @@ fn move_tail(next_head : &Point, tail : &Point, dir : char) -> Point {
             // *HH    *HH
             // **H => *TH
             // T**    ***
-            if (next_head.x >= next_tail.x + 1) && (next_head.y <= next_tail.y -1 ) {
-                next_tail.x += 1;
-                next_tail.y -= 1;
+            if (next_head.x() >= next_tail.x() + 1) && (next_head.y() <= next_tail.y() -1 ) {
+                *next_tail.x_mut() += 1;
+                *next_tail.y_mut() -= 1;
             }
             // HH*    HH*
             // H** => HT*
             // **T    ***
-            else if (next_head.x <= next_tail.x - 1) && (next_head.y <= next_tail.y - 1) {
-                next_tail.x -= 1;
-                next_tail.y -= 1;
+            else if (next_head.x() <= next_tail.x() - 1) && (next_head.y() <= next_tail.y() - 1) {
+                *next_tail.x_mut() -= 1;
+                *next_tail.y_mut() -= 1;
             }
             // **T    ***
             // H** => HT*
             // HH*    HH*
-            else if (next_head.x <= next_tail.x - 1) && (next_head.y >= next_tail.y + 1) {
-                next_tail.x -= 1;
-                next_tail.y += 1;
+            else if (next_head.x() <= next_tail.x() - 1) && (next_head.y() >= next_tail.y() + 1) {
+                *next_tail.x_mut() -= 1;
+                *next_tail.y_mut() += 1;
             }
             // T**    ***
             // **H => *TH
             // *HH    *HH
-            else if (next_head.x >= next_tail.x + 1) && (next_head.y >= next_tail.y + 1) {
-                next_tail.x += 1;
-                next_tail.y += 1;
+            else if (next_head.x() >= next_tail.x() + 1) && (next_head.y() >= next_tail.y() + 1) {
+                *next_tail.x_mut() += 1;
+                *next_tail.y_mut() += 1;
             }
         }
     }
@@ -1,11 +1,12 @@
-use crate::utils::{self};
-use crate::algebra::{Point2D, SparseMatrix};
+use crate::utils::{self, as_usize};
+use crate::algebra::{self, Point2D, SparseMatrix};
 use std::{path::Path};
 
 const ROCK : char = '#';
 const SAND : char = 'o';
 
 type Matrix = SparseMatrix<char>;
+type Grid = algebra::Matrix<char>;
 
 fn parse_matrix<P>(filename : P) -> Matrix
 where P : AsRef<Path> {
@@ -32,49 +33,49 @@ where P : AsRef<Path> {
                     let end = &points[i+1];
 
                     // vertical line
-                    if start.x == end.x {
-                        if start.y <= end.y {
-                            for y in start.y..=end.y {
-                                matrix.insert(start.x, y, ROCK);
+                    if start.x() == end.x() {
+                        if start.y() <= end.y() {
+                            for y in start.y()..=end.y() {
+                                matrix.insert(start.x(), y, ROCK);
                             }
                         }
                         else {
-                            for y in end.y..=start.y {
-                                matrix.insert(start.x, y, ROCK);
+                            for y in end.y()..=start.y() {
+                                matrix.insert(start.x(), y, ROCK);
                             }
                         }
                     }
                     // horizontal line
-                    else if start.y == end.y { 
-                        if start.x <= end.x {
-                            for x in start.x..=end.x {
-                                matrix.insert(x, start.y, ROCK);
+                    else if start.y() == end.y() { 
+                        if start.x() <= end.x() {
+                            for x in start.x()..=end.x() {
+                                matrix.insert(x, start.y(), ROCK);
                             }
                         }
                         else {
-                            for x in end.x..=start.x {
-                                matrix.insert(x, start.y, ROCK);
+                            for x in end.x()..=start.x() {
+                                matrix.insert(x, start.y(), ROCK);
                             }
                         }
                     }
                     
-                    if start.y > matrix.bottom_most {
-                        matrix.bottom_most = start.y;
+                    if start.y() > matrix.bottom_most {
+                        matrix.bottom_most = start.y();
                     }
-                    if end.y > matrix.bottom_most {
-                        matrix.bottom_most = end.y;
+                    if end.y() > matrix.bottom_most {
+                        matrix.bottom_most = end.y();
                     }
-                    if start.x < matrix.left_most {
-                        matrix.left_most = start.x;
+                    if start.x() < matrix.left_most {
+                        matrix.left_most = start.x();
                     }
-                    if start.x > matrix.right_most {
-                        matrix.right_most = start.x;
+                    if start.x() > matrix.right_most {
+                        matrix.right_most = start.x();
                     }
-                    if end.x < matrix.left_most {
-                        matrix.left_most = end.x;
+                    if end.x() < matrix.left_most {
+                        matrix.left_most = end.x();
                     }
-                    if end.x > matrix.right_most {
-                        matrix.right_most = end.x;
+                    if end.x() > matrix.right_most {
+                        matrix.right_most = end.x();
                     }
                 }                
             }
@@ -84,97 +85,283 @@ where P : AsRef<Path> {
     matrix
 }
 
-fn find_units_of_sand(matrix : &mut Matrix) -> i32 {
-    let mut count = 0;
+const AIR : char = '.';
+const SOURCE : char = '+';
 
-    loop {
-        let mut x = 500;
-        let mut y = 0;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FloorMode {
+    // the simulation stops when the first grain falls below the lowest rock
+    Abyss,
+    // an infinite floor at the given distance below the lowest rock
+    Floor(i32),
+    // grains falling below the lowest rock are lost and the simulation goes on
+    None
+}
 
-        while y < matrix.bottom_most {
-            if let None = matrix.element_at(x, y+1) {
-                y += 1;
-                continue;
-            }
-            if let None = matrix.element_at(x - 1, y + 1) {
-                y += 1;
-                x -= 1;
-                continue;
-            }
-            if let None = matrix.element_at(x + 1, y + 1) {
-                y += 1;
-                x += 1;
-                continue;
-            }            
+#[derive(Clone, Copy, Debug)]
+struct SandConfig {
+    source : Point2D,
+    floor : FloorMode,
+    // the maximum number of grains dropped from the source, if any
+    max_grains : Option<usize>
+}
+
+impl SandConfig {
+    fn new(floor : FloorMode) -> SandConfig {
+        SandConfig { source : Point2D::new(500, 0), floor, max_grains : None }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct SandStats {
+    resting : usize,
+    lost : usize
+}
 
+// dense copy of the scan, from the source down to the floor (or the lowest rock),
+// wide enough for the sand to spread from the source
+struct Cave {
+    grid : Grid,
+    left_most : i32,
+    top_most : i32,
+    bottom_most : i32,
+    source : Point2D,
+    floor : Option<i32>
+}
+
+fn build_cave(matrix : &Matrix, config : &SandConfig) -> Cave {
+    let source = config.source;
+    let floor = match config.floor {
+        FloorMode::Floor(k) => Some(matrix.bottom_most + k),
+        _ => None
+    };
+
+    // the last row a grain can be in
+    let bottom = floor.map_or(matrix.bottom_most, |f| f - 1).max(source.y());
+    let spread = bottom - source.y() + 1;
+    let left_most = matrix.left_most.min(source.x() - spread);
+    let right_most = matrix.right_most.max(source.x() + spread);
+
+    let rows = as_usize(bottom - source.y() + 1);
+    let cols = as_usize(right_most - left_most + 1);
+    let mut cave = Cave {
+        grid : Grid::new_from(rows, cols, vec![AIR; rows * cols]),
+        left_most,
+        top_most : source.y(),
+        bottom_most : matrix.bottom_most,
+        source,
+        floor
+    };
+
+    for (p, c) in &matrix.points {
+        if cave.contains(p.x(), p.y()) {
+            cave.set_at(p.x(), p.y(), *c);
+        }
+    }
+
+    cave
+}
+
+impl Cave {
+    fn contains(&self, x : i32, y : i32) -> bool {
+        x >= self.left_most && y >= self.top_most &&
+        as_usize(x - self.left_most) < self.grid.cols && as_usize(y - self.top_most) < self.grid.rows
+    }
+
+    fn element_at(&self, x : i32, y : i32) -> Option<char> {
+        if !self.contains(x, y) {
+            return None;
+        }
+        self.grid.element_at(as_usize(y - self.top_most), as_usize(x - self.left_most))
+    }
+
+    fn is_free(&self, x : i32, y : i32) -> bool {
+        self.element_at(x, y) == Some(AIR)
+    }
+
+    fn set_at(&mut self, x : i32, y : i32, value : char) {
+        self.grid.set_at(as_usize(y - self.top_most), as_usize(x - self.left_most), value);
+    }
+}
+
+// drop grains from the source until the limit is reached, the source is blocked or (with the abyss) a grain is lost;
+// the fall path is kept on a stack, so every grain starts from where the previous one was still falling
+fn drop_sand(cave : &mut Cave, config : &SandConfig) -> SandStats {
+    let mut stats = SandStats { resting : 0, lost : 0 };
+    let mut path = Vec::new();
+    if cave.is_free(cave.source.x(), cave.source.y()) {
+        path.push((cave.source.x(), cave.source.y()));
+    }
+
+    while let Some(&(x, y)) = path.last() {
+        if Some(stats.resting) == config.max_grains {
             break;
         }
 
-        if y == matrix.bottom_most {
+        if cave.floor.is_none() && y >= cave.bottom_most {
+            // every following grain takes the same path, so they are all lost;
+            // without a limit the simulation stops after the first one
+            if config.floor == FloorMode::None {
+                stats.lost = config.max_grains.map_or(1, |max| max - stats.resting);
+            }
             break;
         }
 
-        if !matrix.try_insert(x, y, SAND) {
-            panic!("Cannot insert unit!");
+        let next = if Some(y + 1) == cave.floor {
+            None
+        }
+        else {
+            [x, x - 1, x + 1].into_iter().find(|&nx| cave.is_free(nx, y + 1))
+        };
+
+        match next {
+            Some(nx) => path.push((nx, y + 1)),
+            None => {
+                cave.set_at(x, y, SAND);
+                path.pop();
+                stats.resting += 1;
+            }
         }
-        count += 1;
-    }    
+    }
 
-    count
+    stats
 }
 
-fn find_units_of_sand2(matrix : &mut Matrix) -> i32 {
-    let mut count = 0;
+fn simulate(matrix : &Matrix, config : &SandConfig) -> (Cave, SandStats) {
+    let mut cave = build_cave(matrix, config);
+    let stats = drop_sand(&mut cave, config);
+    (cave, stats)
+}
 
-    loop {
-        let mut x = 500;
-        let mut y = 0;
+fn find_units_of_sand(matrix : &Matrix) -> usize {
+    simulate(matrix, &SandConfig::new(FloorMode::Abyss)).1.resting
+}
 
-        while y < matrix.bottom_most+1 {
-            if let None = matrix.element_at(x, y+1) {
-                y += 1;
-                continue;
-            }
-            if let None = matrix.element_at(x - 1, y + 1) {
-                y += 1;
-                x -= 1;
-                continue;
-            }
-            if let None = matrix.element_at(x + 1, y + 1) {
-                y += 1;
-                x += 1;
-                continue;
-            }
+fn find_units_of_sand2(matrix : &Matrix) -> usize {
+    simulate(matrix, &SandConfig::new(FloorMode::Floor(2))).1.resting
+}
 
-            break;
+// with a floor, sand ends up in every cell reachable from the source, so they can be counted row by row:
+// a cell gets sand if it is not rock and one of the three cells above it got sand
+fn count_reachable_sand(matrix : &Matrix, config : &SandConfig) -> Option<usize> {
+    if !matches!(config.floor, FloorMode::Floor(_)) {
+        return None;
+    }
+
+    let cave = build_cave(matrix, config);
+    if !cave.is_free(cave.source.x(), cave.source.y()) {
+        return Some(0);
+    }
+
+    let cols = cave.grid.cols;
+    let mut row = vec![false; cols];
+    row[as_usize(cave.source.x() - cave.left_most)] = true;
+    let mut count = 1;
+
+    for y in 1..cave.grid.rows {
+        let mut next = vec![false; cols];
+        for x in 0..cols {
+            let from_above = row[x] || (x > 0 && row[x - 1]) || (x + 1 < cols && row[x + 1]);
+            next[x] = from_above && cave.grid.element_at(y, x) == Some(AIR);
         }
 
-        if y == 0 {
-            break;
+        count += next.iter().filter(|&&c| c).count();
+        row = next;
+    }
+
+    Some(count)
+}
+
+// draw the window [min, max] as in the puzzle text
+fn render_window(cave : &Cave, min : &Point2D, max : &Point2D) -> String {
+    let mut text = String::new();
+
+    for y in min.y()..=max.y() {
+        for x in min.x()..=max.x() {
+            let c = if Some(y) == cave.floor {
+                ROCK
+            }
+            else if x == cave.source.x() && y == cave.source.y() && cave.is_free(x, y) {
+                SOURCE
+            }
+            else {
+                cave.element_at(x, y).unwrap_or(AIR)
+            };
+            text.push(c);
         }
+        text.push('\n');
+    }
 
-        if !matrix.try_insert(x, y, SAND) {
-            panic!("Cannot insert unit!");
+    text
+}
+
+// draw the smallest window that shows the source, the rocks, the sand and the floor
+fn render(cave : &Cave) -> String {
+    let mut min = cave.source;
+    let mut max = cave.source;
+
+    for r in 0..cave.grid.rows {
+        for c in 0..cave.grid.cols {
+            if cave.grid.element_at(r, c) != Some(AIR) {
+                let x = utils::as_i32(c) + cave.left_most;
+                let y = utils::as_i32(r) + cave.top_most;
+                min = Point2D::new(min.x().min(x), min.y().min(y));
+                max = Point2D::new(max.x().max(x), max.y().max(y));
+            }
         }
-        count += 1;
-    }    
+    }
 
-    count + 1
+    if let Some(floor) = cave.floor {
+        *max.y_mut() = floor;
+    }
+
+    render_window(cave, &min, &max)
 }
 
 pub fn execute() {
     println!("=== puzzle 14 ===");
 
     let test_matrix = parse_matrix("./data/input14test.txt");
-    assert_eq!(24, find_units_of_sand(&mut test_matrix.clone()));
+    assert_eq!(24, find_units_of_sand(&test_matrix));
 
     let matrix = parse_matrix("./data/input14.txt");
-    let count = find_units_of_sand(&mut matrix.clone());
+    let count = find_units_of_sand(&matrix);
     println!("units={}", count);
 
-    assert_eq!(93, find_units_of_sand2(&mut test_matrix.clone()));
+    let (test_cave, _) = simulate(&test_matrix, &SandConfig::new(FloorMode::Abyss));
+    assert_eq!(concat!(
+        "......+...\n",
+        "..........\n",
+        "......o...\n",
+        ".....ooo..\n",
+        "....#ooo##\n",
+        "...o#ooo#.\n",
+        "..###ooo#.\n",
+        "....oooo#.\n",
+        ".o.ooooo#.\n",
+        "#########.\n"), render(&test_cave));
+
+    assert_eq!(93, find_units_of_sand2(&test_matrix));
+    assert_eq!(Some(93), count_reachable_sand(&test_matrix, &SandConfig::new(FloorMode::Floor(2))));
+
+    let (test_cave2, _) = simulate(&test_matrix, &SandConfig::new(FloorMode::Floor(2)));
+    println!("{}", render(&test_cave2));
+
+    // no floor: the 25th grain is the first one to fall through
+    let config = SandConfig { max_grains : Some(30), ..SandConfig::new(FloorMode::None) };
+    assert_eq!(SandStats { resting : 24, lost : 6 }, simulate(&test_matrix, &config).1);
+
+    assert_eq!(SandStats { resting : 24, lost : 1 }, simulate(&test_matrix, &SandConfig::new(FloorMode::None)).1);
+
+    let config = SandConfig { max_grains : Some(10), ..SandConfig::new(FloorMode::Abyss) };
+    assert_eq!(SandStats { resting : 10, lost : 0 }, simulate(&test_matrix, &config).1);
+
+    // the row by row count matches the simulation for any source
+    let config = SandConfig { source : Point2D::new(502, 0), ..SandConfig::new(FloorMode::Floor(2)) };
+    assert_eq!(count_reachable_sand(&test_matrix, &config), Some(simulate(&test_matrix, &config).1.resting));
 
-    let count2 = find_units_of_sand2(&mut matrix.clone());
+    let count2 = find_units_of_sand2(&matrix);
+    assert_eq!(Some(count2), count_reachable_sand(&matrix, &SandConfig::new(FloorMode::Floor(2))));
     println!("units={}", count2);
     
     println!();
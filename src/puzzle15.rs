@@ -1,6 +1,6 @@
 use regex::Regex;
-use crate::{utils::{self}, algebra::{SparseMatrix, Point2D}};
-use std::{path::Path, collections::HashMap};
+use crate::{utils::{self}, algebra::{self, SparseMatrix, Point2D, Diamond, IntervalSet}};
+use std::{env, path::Path, collections::HashMap};
 
 const SENSOR : char = 'S';
 const BEACON : char = 'B';
@@ -69,169 +69,232 @@ where P : AsRef<Path> {
     (matrix, snb)
 }
 
-fn manhattan_distance(p1 : &Point2D, p2 : &Point2D) -> i32 {
-    i32::abs(p1.x - p2.x) + i32::abs(p1.y - p2.y)
-}
-
 fn find_impossible_positions(matrix : &mut Matrix, snb : &SensorsAndBeacons, line : i32) -> usize {
     for (sensor, beacon) in &*snb {
-        let mdist = manhattan_distance(&sensor, &beacon);
+        let mdist = sensor.manhattan(beacon);
 
         for dy in 0..=mdist {
             let y = mdist - dy;
             for dx in -dy..=dy {                
-                if let None = matrix.element_at(sensor.x + dx, sensor.y - y) {
-                    matrix.try_insert(sensor.x + dx, sensor.y - y, IMPOSIBLE_POSITION);
+                if let None = matrix.element_at(sensor.x() + dx, sensor.y() - y) {
+                    matrix.try_insert(sensor.x() + dx, sensor.y() - y, IMPOSIBLE_POSITION);
                 }            
             }
         }
         for dy in 1..=mdist {
             for dx in -(mdist-dy)..=(mdist-dy) {
-                if let None = matrix.element_at(sensor.x + dx, sensor.y + dy) {
-                    matrix.try_insert(sensor.x + dx, sensor.y + dy, IMPOSIBLE_POSITION);
+                if let None = matrix.element_at(sensor.x() + dx, sensor.y() + dy) {
+                    matrix.try_insert(sensor.x() + dx, sensor.y() + dy, IMPOSIBLE_POSITION);
                 }
             }
         }
     }
 
-    matrix.points.iter().filter(|&(k,v)|k.y == line && *v == IMPOSIBLE_POSITION).count()
+    matrix.points.iter().filter(|&(k,v)|k.y() == line && *v == IMPOSIBLE_POSITION).count()
 }
 
-fn find_impossible_positions_smart(matrix : &mut Matrix, snb : &SensorsAndBeacons, line : i32) -> usize {
-    for (sensor, beacon) in &*snb {
-        let mdist = manhattan_distance(&sensor, &beacon);
-
-        if sensor.y - mdist <= line && line <= sensor.y + mdist {
-            if line <= sensor.y {
-                let y = sensor.y - line;
-                let dy = mdist - y;
-                for dx in -dy..=dy {                
-                    if let None = matrix.element_at(sensor.x + dx, sensor.y - y) {
-                        matrix.try_insert(sensor.x + dx, sensor.y - y, IMPOSIBLE_POSITION);
-                    }            
-                }
-            }
-            else {
-                let dy = line - sensor.y;
-                for dx in -(mdist-dy)..=(mdist-dy) {
-                    if let None = matrix.element_at(sensor.x + dx, sensor.y + dy) {
-                        matrix.try_insert(sensor.x + dx, sensor.y + dy, IMPOSIBLE_POSITION);
-                    }
-                }
-            }
+// the area covered by each sensor
+fn sensor_diamonds(snb : &SensorsAndBeacons) -> Vec<Diamond> {
+    snb.iter().map(|(sensor, beacon)| Diamond::reaching(*sensor, beacon)).collect()
+}
+
+// the cells of the row covered by at least one sensor
+fn row_coverage(snb : &SensorsAndBeacons, line : i32) -> IntervalSet<i32> {
+    IntervalSet::from_intervals(sensor_diamonds(snb).iter().filter_map(|d| d.row_span(line)))
+}
+
+fn find_impossible_positions_smart(snb : &SensorsAndBeacons, line : i32) -> usize {
+    let coverage = row_coverage(snb, line);
+
+    // sensors and beacons on the row are covered, but they are not impossible positions
+    let mut devices : Vec<i32> = snb.iter()
+        .flat_map(|(sensor, beacon)| [sensor, beacon])
+        .filter(|p| p.y() == line)
+        .map(|p| p.x())
+        .collect();
+    devices.sort();
+    devices.dedup();
+
+    let occupied = devices.iter().filter(|&&x| coverage.contains(x)).count();
+
+    usize::try_from(coverage.len()).unwrap() - occupied
+}
+
+// every point of the square search area [0, max] x [0, max] that no sensor covers;
+// each row is swept with its merged coverage, so no uncovered point can be missed
+fn find_uncovered_points_sweep(snb : &SensorsAndBeacons, max : i32) -> Vec<Point2D> {
+    let mut points = Vec::new();
+
+    for y in 0..=max {
+        for &(lo, hi) in row_coverage(snb, y).gaps_within(0, max).intervals() {
+            points.extend((lo..=hi).map(|x| Point2D::new(x, y)));
         }
     }
 
-    matrix.points.iter().filter(|&(k,v)|k.y == line && *v == IMPOSIBLE_POSITION).count()
+    points
+}
+
+// same as above, but only the cells between the edges of the diamonds (in rotated coordinates) are inspected
+fn find_uncovered_points(snb : &SensorsAndBeacons, max : i32) -> Vec<Point2D> {
+    algebra::uncovered_points(&sensor_diamonds(snb), &Point2D::new(0, 0), &Point2D::new(max, max))
 }
 
-struct Sensor {
-    position : Point2D,
-    range : i32
+// the frequency of the distress beacon, if there is exactly one uncovered point in the search area
+fn find_tunning_frequency(snb : &SensorsAndBeacons, max : i32, multiplier : i64) -> Option<i64> {
+    tunning_frequency(&find_uncovered_points(snb, max), multiplier)
 }
 
-fn sign(value : i32) -> i32 {
-    if value < 0 {-1} else {1}
+// the frequency of the distress beacon, if it is the only uncovered point
+fn tunning_frequency(uncovered : &[Point2D], multiplier : i64) -> Option<i64> {
+    match uncovered {
+        [p] => Some(p.x() as i64 * multiplier + p.y() as i64),
+        _ => None
+    }
 }
 
-fn find_tunning_frequency(snb : &SensorsAndBeacons) -> i64 {
-    /*
-    Look at each pair of sensors and check if their covering range intersects.
-    If there is exactly one line between them (their Manhattan's distance is equal to sum of their coverage area + 2)
-    then check that line to see if any other scanner is covering it.
-    If there is a point along that line not covered by any other sensor, that's the missing beacon!
-
-    ............
-    ....1.......
-    ...111......
-    ..11111.....
-    .111S111....
-    ..11111@2...
-    ...111@222..
-    ....1@22S22.
-    .......222..
-    ........2...
-    ............
-
-    1 - the coverage area of sensor 1.
-    2 - the coverage area of sensor 2
-    @ - point on a line between two neighbors
-
-    mdist(sensor1, beacon1) = 3
-    mdist(sensor2, beacon2) = 2
-    mdist(sensor1, sensor2) = 7
-
-    Condition:
-    mdist(sensor1, beacon1) + mdist(sensor2, beacon2) + 2 = mdist(sensor1, sensor2)
-     */
-
-    let mut sensors : Vec<Sensor> = Vec::new();
+const UNCOVERED : char = '.';
+const HIGHLIGHT : char = '@';
 
-    for (sensor, beacon) in &*snb {
-        sensors.push(Sensor{position : *sensor, range : manhattan_distance(sensor, beacon)});
+fn cell_at(snb : &SensorsAndBeacons, diamonds : &[Diamond], highlights : &[Point2D], p : &Point2D) -> char {
+    if highlights.contains(p) {
+        HIGHLIGHT
+    }
+    else if snb.contains_key(p) {
+        SENSOR
+    }
+    else if snb.values().any(|b| b == p) {
+        BEACON
+    }
+    else if diamonds.iter().any(|d| d.contains(p)) {
+        IMPOSIBLE_POSITION
+    }
+    else {
+        UNCOVERED
     }
+}
 
-    let mut dx = 0;
-    let mut dy = 0;
-
-    'outer: 
-    for i in 0..sensors.len() {
-        for j in i+1..sensors.len() {
-            let mdist = manhattan_distance(&sensors[i].position, &sensors[j].position);
-            if mdist == sensors[i].range + sensors[j].range + 2 {
-                let x1 = sensors[i].position.x + sign(sensors[j].position.x - sensors[i].position.x) * (sensors[i].range+1);
-                let y1 = sensors[i].position.y;
-
-                let x2 = sensors[i].position.x;
-                let y2 = sensors[i].position.y + sign(sensors[j].position.y - sensors[i].position.y) * (sensors[i].range+1);
-
-                // check every point between the coverage ranges
-                dx = x1;
-                dy = y1;
-                while dx != x2 && dy != y2 {
-                    // check if the point is out of scan of any other scanner
-
-                    let mut good = true;
-                    for k in 0..sensors.len() {
-                        if manhattan_distance(&Point2D::new(dx, dy), &sensors[k].position) <= sensors[k].range {
-                            good = false;
-                            break;
-                        }
-                    }
-
-                    if good {
-                        break 'outer;
-                    }
-
-                    dx += sign(x2 - x1);
-                    dy += sign(y2 - y1);
-                }
-            }
+// draw the window [min, max] as in the puzzle text, with the highlighted points (e.g. the distress beacon) as '@'
+fn render_window(snb : &SensorsAndBeacons, min : &Point2D, max : &Point2D, highlights : &[Point2D]) -> String {
+    let diamonds = sensor_diamonds(snb);
+    let mut text = String::new();
+
+    for y in min.y()..=max.y() {
+        for x in min.x()..=max.x() {
+            text.push(cell_at(snb, &diamonds, highlights, &Point2D::new(x, y)));
         }
+        text.push('\n');
     }
 
-    dx as i64 * 4000000 + dy as i64
+    text
+}
+
+// a binary PPM image of the window [min, max] scaled down to width x height pixels;
+// every pixel shows the cell at its center, while sensors, beacons and highlights are always drawn
+fn render_ppm(snb : &SensorsAndBeacons, min : &Point2D, max : &Point2D, width : usize, height : usize, highlights : &[Point2D]) -> Vec<u8> {
+    let diamonds = sensor_diamonds(snb);
+    let span_x = (max.x() - min.x() + 1) as i64;
+    let span_y = (max.y() - min.y() + 1) as i64;
+
+    let color = |c : char| -> [u8; 3] {
+        match c {
+            HIGHLIGHT => [255, 0, 0],
+            SENSOR => [0, 160, 0],
+            BEACON => [0, 0, 255],
+            IMPOSIBLE_POSITION => [64, 64, 64],
+            _ => [255, 255, 255]
+        }
+    };
+
+    let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    if width == 0 || height == 0 {
+        return image;
+    }
+
+    let mut pixels = vec![[0u8; 3]; width * height];
+    for py in 0..height {
+        for px in 0..width {
+            let x = min.x() as i64 + (2 * px as i64 + 1) * span_x / (2 * width as i64);
+            let y = min.y() as i64 + (2 * py as i64 + 1) * span_y / (2 * height as i64);
+            let p = Point2D::new(x as i32, y as i32);
+            pixels[py * width + px] = color(cell_at(snb, &diamonds, &[], &p));
+        }
+    }
+
+    // single cells are lost when scaling down, so draw them on top
+    let devices = snb.iter().flat_map(|(s, b)| [(*s, SENSOR), (*b, BEACON)]);
+    let marks = devices.chain(highlights.iter().map(|h| (*h, HIGHLIGHT)));
+    for (p, c) in marks {
+        if min.x() <= p.x() && p.x() <= max.x() && min.y() <= p.y() && p.y() <= max.y() {
+            let px = (p.x() - min.x()) as i64 * width as i64 / span_x;
+            let py = (p.y() - min.y()) as i64 * height as i64 / span_y;
+            pixels[py as usize * width + px as usize] = color(c);
+        }
+    }
+
+    image.extend(pixels.iter().flatten());
+    image
 }
 
 pub fn execute() {
     println!("=== puzzle 15 ===");
 
     let (test_matrix, test_snb) = parse_matrix("./data/input15test.txt");
-    let test_count = find_impossible_positions(&mut test_matrix.clone(), &test_snb, 10);
+    let distress = Point2D::new(14, 11);
+
+    let mut test_coverage = test_matrix.clone();
+    let test_count = find_impossible_positions(&mut test_coverage, &test_snb, 10);
     assert_eq!(26, test_count);
 
-    let test_count2 = find_impossible_positions_smart(&mut test_matrix.clone(), &test_snb, 10);
+    // every sensor, beacon and impossible position is covered by a diamond
+    let test_diamonds = sensor_diamonds(&test_snb);
+    assert_eq!(test_coverage.points.len() as i64, algebra::diamond_union_area(&test_diamonds));
+
+    let test_count2 = find_impossible_positions_smart(&test_snb, 10);
     assert_eq!(26, test_count2);
+    assert_eq!(&[(-2, 24)], row_coverage(&test_snb, 10).intervals());
+    assert_eq!(&[(-3, 13), (15, 25)], row_coverage(&test_snb, 11).intervals());
+    assert_eq!(vec![(14, 14)], row_coverage(&test_snb, 11).gaps().collect::<Vec<_>>());
+
+    let test_picture = render_window(&test_snb, &Point2D::new(-4, 0), &Point2D::new(26, 22), &[distress]);
+    let test_rows : Vec<&str> = test_picture.lines().collect();
+    assert_eq!("..####B######################..", test_rows[10]);
+    println!("{}", test_picture);
 
-    let (matrix, snb) = parse_matrix("./data/input15.txt");
-    let count = find_impossible_positions_smart(&mut matrix.clone(), &snb, 2000000);
+    // one pixel per cell, so the distress beacon is the red pixel at (14, 11)
+    let test_image = render_ppm(&test_snb, &Point2D::new(0, 0), &Point2D::new(20, 20), 21, 21, &[distress]);
+    let header = "P6\n21 21\n255\n".len();
+    assert_eq!(header + 3 * 21 * 21, test_image.len());
+    assert_eq!(&[255, 0, 0], &test_image[header + 3 * (11 * 21 + 14)..][..3]);
+
+    assert_eq!(b"P6\n0 21\n255\n".to_vec(), render_ppm(&test_snb, &Point2D::new(0, 0), &Point2D::new(20, 20), 0, 21, &[]));
+
+    let (_, snb) = parse_matrix("./data/input15.txt");
+    let count = find_impossible_positions_smart(&snb, 2000000);
     println!("count={}", count);
 
-    let test_freq = find_tunning_frequency(&test_snb);
-    assert_eq!(56000011, test_freq);
+    assert_eq!(vec![Point2D::new(14, 11)], find_uncovered_points_sweep(&test_snb, 20));
+    assert_eq!(vec![Point2D::new(14, 11)], find_uncovered_points(&test_snb, 20));
+    assert_eq!(find_uncovered_points_sweep(&test_snb, 30), find_uncovered_points(&test_snb, 30));
+
+    // the distress beacon sits where the edges just outside two sensor ranges cross
+    let test_edges : Vec<_> = test_diamonds.iter()
+        .flat_map(|d| Diamond::new(d.center, d.radius + 1).boundary())
+        .collect();
+    assert!(test_diamonds.iter().all(|d| !d.contains(&distress)));
+    assert!(test_edges.iter().any(|a| test_edges.iter().any(|b| a.intersection(b) == Some(distress))));
 
-    let freq = find_tunning_frequency(&snb);
+    let test_freq = find_tunning_frequency(&test_snb, 20, 4000000);
+    assert_eq!(Some(56000011), test_freq);
+
+    let uncovered = find_uncovered_points(&snb, 4000000);
+    let freq = tunning_frequency(&uncovered, 4000000).unwrap();
     println!("freq={}", freq);
 
+    // the whole search area scaled down, with the distress beacon in red
+    if env::var_os("AOC_EXPORT_DIR").is_some() {
+        let image = render_ppm(&snb, &Point2D::new(0, 0), &Point2D::new(4000000, 4000000), 800, 800, &uncovered);
+        utils::export_file("coverage15.ppm", image);
+    }
+
     println!();
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use crate::utils;
-use std::{path::Path, collections::{HashMap, hash_map::Entry}};
+use std::path::Path;
 
 #[derive(Clone, Copy)]
 enum JetDirection {
@@ -29,11 +29,12 @@ impl Rock {
         self.0 & mask != 0
     }
 
-    fn shift(&mut self, dir : JetDirection, mask : u32) {
+    // left_wall has the bit of the leftmost column set in every byte (0x40404040 for 7 columns)
+    fn shift(&mut self, dir : JetDirection, mask : u32, left_wall : u32) {
         let pos = match dir {
             JetDirection::Left => {
-                // wall is at 0x80; if left cells are at 0x40 then it cannot shift left
-                if self.0 & 0x40404040 == 0 {
+                // if left cells are at the leftmost column then it cannot shift left
+                if self.0 & left_wall == 0 {
                     self.0 << 1
                 } 
                 else {
@@ -62,7 +63,174 @@ impl Rock {
     }
 }
 
-type RockCollection = [Rock;5];
+const STANDARD_SHAPES : &str = "\
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##";
+
+// a rock drawn as in the puzzle text; rows are stored bottom up, with bit c set for column c from the left
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shape {
+    rows : Vec<u64>,
+    width : usize
+}
+
+// None for other characters than '#' and '.', or for shapes wider than 64 columns
+fn parse_shape(picture : &str) -> Option<Shape> {
+    let mut rows = Vec::new();
+    let mut width = 0;
+
+    for line in picture.lines().rev() {
+        let mut row = 0u64;
+        for (c, ch) in line.trim().chars().enumerate() {
+            match ch {
+                '#' if c < 64 => {
+                    row |= 1 << c;
+                    width = width.max(c + 1);
+                },
+                '.' => {},
+                _ => return None
+            }
+        }
+        rows.push(row);
+    }
+
+    Some(Shape { rows, width })
+}
+
+// shapes are separated by empty lines
+fn parse_shapes(text : &str) -> Option<Vec<Shape>> {
+    text.split("\n\n").filter(|p| !p.trim().is_empty()).map(parse_shape).collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ChamberConfig {
+    width : usize,
+    // empty columns between the left wall and a new rock
+    spawn_left : usize,
+    // empty rows between the highest rock and a new rock
+    spawn_above : usize
+}
+
+impl ChamberConfig {
+    // true if the shape spawns inside the chamber
+    fn fits(&self, shape : &Shape) -> bool {
+        self.spawn_left + shape.width <= self.width
+    }
+}
+
+impl Default for ChamberConfig {
+    fn default() -> Self {
+        ChamberConfig { width : 7, spawn_left : 2, spawn_above : 3 }
+    }
+}
+
+impl Shape {
+    // the packed representation of the shape at its spawn position, if it fits the fast path:
+    // one byte per row (at most 4 rows, none of them empty), with the leftmost column on the highest bit of the chamber width
+    fn to_rock(&self, config : &ChamberConfig) -> Option<Rock> {
+        if config.width > 8 || self.rows.len() > 4 || self.rows.contains(&0) || !config.fits(self) {
+            return None;
+        }
+
+        let mut packed = 0u32;
+        for (i, row) in self.rows.iter().enumerate() {
+            let mut byte = 0u32;
+            for c in 0..self.width {
+                if row & (1 << c) != 0 {
+                    byte |= 1 << (config.width - 1 - config.spawn_left - c);
+                }
+            }
+            packed |= byte << (8 * i);
+        }
+
+        Some(Rock(packed))
+    }
+}
+
+// a chamber of any width up to 64; row y has bit c set if column c is taken
+type WideCave = Vec<u64>;
+
+fn collides(cave : &WideCave, shape : &Shape, x : i64, y : i64, width : usize) -> bool {
+    if x < 0 || y < 0 || x as usize + shape.width > width {
+        return true;
+    }
+
+    shape.rows.iter().enumerate().any(|(i, row)| {
+        let level = y as usize + i;
+        level < cave.len() && cave[level] & (row << x) != 0
+    })
+}
+
+fn simulate_shape(cave : &mut WideCave, jets : &JetVector, mut jet_index : usize, shape : &Shape, config : &ChamberConfig) -> usize {
+    let mut x = config.spawn_left as i64;
+    let mut y = (cave.len() + config.spawn_above) as i64;
+
+    loop {
+        let dx = match jets[jet_index] {
+            JetDirection::Left => -1,
+            JetDirection::Right => 1
+        };
+        jet_index = (jet_index + 1) % jets.len();
+
+        if !collides(cave, shape, x + dx, y, config.width) {
+            x += dx;
+        }
+
+        if collides(cave, shape, x, y - 1, config.width) {
+            break;
+        }
+        y -= 1;
+    }
+
+    for (i, row) in shape.rows.iter().enumerate() {
+        let level = y as usize + i;
+        if level >= cave.len() {
+            cave.resize(level + 1, 0);
+        }
+        cave[level] |= row << x;
+    }
+
+    jet_index
+}
+
+fn eval_shape_tower_height(jets : &JetVector, shapes : &[Shape], total_rocks : usize, config : &ChamberConfig) -> usize {
+    let mut cave : WideCave = Vec::with_capacity(total_rocks * 4);
+    let mut jet_index = 0;
+
+    for shape in shapes.iter().cycle().take(total_rocks) {
+        jet_index = simulate_shape(&mut cave, jets, jet_index, shape, config);
+    }
+
+    cave.len()
+}
+
+// use the packed rocks when every shape fits in them, and the wide chamber otherwise;
+// None if the chamber is wider than 64 columns or a shape would spawn past the right wall
+fn eval_tower_height(jets : &JetVector, shapes : &[Shape], total_rocks : usize, config : &ChamberConfig) -> Option<usize> {
+    if config.width > 64 || !shapes.iter().all(|s| config.fits(s)) {
+        return None;
+    }
+
+    match shapes.iter().map(|s| s.to_rock(config)).collect::<Option<Vec<Rock>>>() {
+        Some(rocks) => Some(eval_rock_tower_height(jets, &rocks, total_rocks, config)),
+        None => Some(eval_shape_tower_height(jets, shapes, total_rocks, config))
+    }
+}
 
 fn cave_structure(cave: &[u8], height: usize) -> u32 {
     if height >= cave.len() {
@@ -76,8 +244,9 @@ fn cave_structure(cave: &[u8], height: usize) -> u32 {
     }
 }
 
-fn simulate_rock(cave : &mut Cave, jets : &JetVector, mut jet_index : usize, mut rock : Rock) -> Option<usize> {
-    let mut height = cave.len() + 3;
+fn simulate_rock(cave : &mut Cave, jets : &JetVector, mut jet_index : usize, mut rock : Rock, config : &ChamberConfig) -> Option<usize> {
+    let mut height = cave.len() + config.spawn_above;
+    let left_wall = 0x01010101 << (config.width - 1);
 
     loop {
         let shift_dir = jets[jet_index];
@@ -92,7 +261,7 @@ fn simulate_rock(cave : &mut Cave, jets : &JetVector, mut jet_index : usize, mut
         let mask = cave_structure(cave, height);
 
         // shift the rock
-        rock.shift(shift_dir, mask);
+        rock.shift(shift_dir, mask, left_wall);
 
         // the rock cannot move anymore
         if height == 0 || rock.intersects(cave_structure(cave, height - 1)) {
@@ -119,66 +288,98 @@ fn simulate_rock(cave : &mut Cave, jets : &JetVector, mut jet_index : usize, mut
     }
 }
 
-fn eval_rock_tower_height(jets : &JetVector, rocks : &RockCollection, total_rocks : usize) -> usize {
+fn eval_rock_tower_height(jets : &JetVector, rocks : &[Rock], total_rocks : usize, config : &ChamberConfig) -> usize {
     let mut cave : Cave = Vec::with_capacity(total_rocks * 4);
     let mut jet_index = 0;
 
-    for block in rocks.into_iter().cycle().take(total_rocks) {
-        jet_index = simulate_rock(&mut cave, jets, jet_index, *block).unwrap();
+    for block in rocks.iter().cycle().take(total_rocks) {
+        jet_index = simulate_rock(&mut cave, jets, jet_index, *block, config).unwrap();
     }
 
     cave.len()
 }
 
-fn eval_rock_tower_height_long_run(jets : &JetVector, rocks : &RockCollection, total_rocks : u64) -> usize {
-    let mut states = HashMap::with_capacity(1024);
-    let mut cave : Cave = Vec::with_capacity(1024);
+// the cycle found by the long run: after `start` rocks, every `length` rocks the tower grows by `height` rows;
+// the next rock and jet to be used are the same at the start of every cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CycleReport {
+    start : u64,
+    length : u64,
+    height : usize,
+    rock_index : usize,
+    jet_index : usize
+}
+
+struct TowerState {
+    cave : Cave,
+    jet_index : usize,
+    rocks_count : usize
+}
+
+fn eval_rock_tower_height_long_run(jets : &JetVector, rocks : &[Rock], total_rocks : u64, config : &ChamberConfig) -> (usize, Option<CycleReport>) {
+    let initial = TowerState { cave : Vec::with_capacity(1024), jet_index : 0, rocks_count : 0 };
+
+    // simulate rock drop
+    let step = |state : &mut TowerState| {
+        let rock = rocks[state.rocks_count % rocks.len()];
+        state.jet_index = simulate_rock(&mut state.cave, jets, state.jet_index, rock, config).unwrap();
+        state.rocks_count += 1;
+    };
+
+    // the state is made from the top 8 rows, the next rock index, and the jet index
+    let key = |state : &TowerState| {
+        let rows = state.cave.len().min(8);
+        let mut top = [0u8; 8];
+        top[..rows].copy_from_slice(&state.cave[state.cave.len() - rows..]);
+        (u64::from_ne_bytes(top), rows, state.rocks_count % rocks.len(), state.jet_index)
+    };
+
+    let height = |state : &TowerState| i64::try_from(state.cave.len()).unwrap();
+
+    let (total_height, cycle) = utils::extrapolate_cycle(initial, step, key, height, total_rocks);
+
+    let report = cycle.map(|c| CycleReport {
+        start : c.start,
+        length : c.length,
+        height : usize::try_from(c.growth).unwrap(),
+        rock_index : c.key.2,
+        jet_index : c.key.3
+    });
+
+    (usize::try_from(total_height).unwrap(), report)
+}
 
-    let rocks_len = u64::try_from(rocks.len()).unwrap();
-    let mut cave_height = 0;
+// the top rows of the chamber after the given number of rocks came to rest, with the next rock at its spawn position
+fn dump_tower(jets : &JetVector, rocks : &[Rock], rocks_count : usize, rows : usize, config : &ChamberConfig) -> String {
+    let mut cave : Cave = Vec::new();
     let mut jet_index = 0;
-    let mut rocks_count = 0;
-    while rocks_count < total_rocks {
-        // find the next rock
-        let rock_index = usize::try_from(rocks_count % rocks_len).unwrap();
 
-        // simulate rock drop
-        jet_index = simulate_rock(&mut cave, jets, jet_index, rocks[rock_index]).unwrap();
-        rocks_count += 1;
+    for block in rocks.iter().cycle().take(rocks_count) {
+        jet_index = simulate_rock(&mut cave, jets, jet_index, *block, config).unwrap();
+    }
 
-        if cave.len() < 8 {
-            continue;
-        }
+    let falling = rocks[rocks_count % rocks.len()];
+    let spawn = cave.len() + config.spawn_above;
+    let top = spawn + falling.bytes().count();
 
-        // check the current state, made from the top 8 rows, the rock index, and the jet index
-        let top = u64::from_ne_bytes(cave[cave.len() - 8..].try_into().unwrap());
-        let state = (top, rock_index, jet_index);
-
-        // check the cached states 
-        match states.entry(state) {
-            // the state was encountered before
-            Entry::Occupied(e) => {
-                let (prev_rocks_count, prev_height) = e.get();
-                // count the rocks in the cycle
-                let num_rocks_in_cycle = rocks_count - prev_rocks_count;
-                // determine how many cycles are left
-                let num_cycles = (total_rocks - rocks_count) / num_rocks_in_cycle;
-                // jump the rock count
-                rocks_count += num_rocks_in_cycle * num_cycles;
-                // increase cave height based on the cave length and number of cycles
-                cave_height += num_cycles * u64::try_from(cave.len() - prev_height).unwrap();
-
-                // erase the cache
-                states.clear();
-            },
-            // the state was not found before
-            Entry::Vacant(e) => {
-                e.insert((rocks_count, cave.len()));
-            }
+    let mut text = String::new();
+    for level in (top.saturating_sub(rows)..top).rev() {
+        let rock_row = if level >= spawn { falling.0.to_le_bytes()[level - spawn] } else { 0 };
+        let cave_row = cave.get(level).copied().unwrap_or(0);
+
+        text.push('|');
+        for c in 0..config.width {
+            let bit = 1 << (config.width - 1 - c);
+            text.push(if rock_row & bit != 0 { '@' } else if cave_row & bit != 0 { '#' } else { '.' });
         }
+        text.push_str("|\n");
     }
 
-    cave.len() + usize::try_from(cave_height).unwrap()
+    if rows >= top {
+        text.push_str(&format!("+{}+\n", "-".repeat(config.width)));
+    }
+
+    text
 }
 
 /*
@@ -188,27 +389,69 @@ fn eval_rock_tower_height_long_run(jets : &JetVector, rocks : &RockCollection, t
 pub fn execute() {
     println!("=== puzzle 17 ===");
 
-    let rocks : RockCollection = [
+    let config = ChamberConfig::default();
+    let shapes = parse_shapes(STANDARD_SHAPES).unwrap();
+    let rocks : Vec<Rock> = shapes.iter().map(|s| s.to_rock(&config).unwrap()).collect();
+    assert_eq!(vec![
         Rock(0x0000001E), 
         Rock(0x00081C08),
         Rock(0x0004041C),
         Rock(0x10101010),
         Rock(0x00001818)
-    ];
+    ], rocks);
 
     let test_jets = get_gas_jets("./data/input17test.txt");
-    let test_height = eval_rock_tower_height(&test_jets, &rocks, 2022);    
+    let test_height = eval_rock_tower_height(&test_jets, &rocks, 2022, &config);    
     assert_eq!(3068, test_height);
+    assert_eq!(3068, eval_shape_tower_height(&test_jets, &shapes, 2022, &config));
+
+    // the packed rocks and the wide chamber agree on every width they both support
+    for width in 4..=8 {
+        let narrow = ChamberConfig { width, spawn_left : 0, ..config };
+        assert_eq!(
+            eval_tower_height(&test_jets, &shapes, 500, &narrow),
+            Some(eval_shape_tower_height(&test_jets, &shapes, 500, &narrow)));
+    }
+
+    // the 4 wide rock would spawn past the right wall
+    let too_narrow = ChamberConfig { width : 7, spawn_left : 5, spawn_above : 3 };
+    assert_eq!(None, eval_tower_height(&test_jets, &shapes, 10, &too_narrow));
+    assert_eq!(None, eval_tower_height(&test_jets, &shapes, 10, &ChamberConfig { width : 65, ..config }));
+    assert!(parse_shape(&"#".repeat(64)).is_some());
+    assert!(parse_shape(&"#".repeat(65)).is_none());
+    assert!(parse_shape("#o#").is_none());
+
+    let wide = ChamberConfig { width : 64, spawn_left : 30, spawn_above : 5 };
+    println!("height (64 wide)={}", eval_tower_height(&test_jets, &shapes, 2022, &wide).unwrap());
 
     let jets = get_gas_jets("./data/input17.txt");
-    let height = eval_rock_tower_height(&jets, &rocks, 2022);
+    let height = eval_tower_height(&jets, &shapes, 2022, &config).unwrap();
     println!("height 1={}", height);
 
-    let test_height_2 = eval_rock_tower_height_long_run(&test_jets, &rocks, 1000000000000);
+    assert_eq!(concat!(
+        "|...@...|\n",
+        "|..@@@..|\n",
+        "|...@...|\n",
+        "|.......|\n",
+        "|.......|\n",
+        "|.......|\n",
+        "|..####.|\n",
+        "+-------+\n"), dump_tower(&test_jets, &rocks, 1, 10, &config));
+    println!("{}", dump_tower(&test_jets, &rocks, 2022, 12, &config));
+
+    let (test_height_2, test_cycle) = eval_rock_tower_height_long_run(&test_jets, &rocks, 1000000000000, &config);
     assert_eq!(1514285714288, test_height_2);
 
-    let height_2 = eval_rock_tower_height_long_run(&jets, &rocks, 1000000000000);
+    // the skip ahead must agree with the brute force once the cycle has been found a few times
+    let test_cycle = test_cycle.unwrap();
+    let total = usize::try_from(test_cycle.start + 5 * test_cycle.length + 3).unwrap();
+    assert_eq!(eval_rock_tower_height(&test_jets, &rocks, total, &config), eval_rock_tower_height_long_run(&test_jets, &rocks, total as u64, &config).0);
+
+    let (height_2, cycle) = eval_rock_tower_height_long_run(&jets, &rocks, 1000000000000, &config);
     println!("height 2={}", height_2);
+    if let Some(c) = cycle {
+        println!("cycle: starts after {} rocks, {} rocks long, +{} rows, rock {}, jet {}", c.start, c.length, c.height, c.rock_index, c.jet_index);
+    }
 
     println!();
 }
\ No newline at end of file
@@ -1,12 +1,10 @@
 use crate::utils;
-use crate::algebra::Matrix3D;
+use crate::algebra::{Matrix3D, Point3D};
 
-use std::{path::Path, collections::HashSet};
+use std::{env, path::Path, collections::HashSet, collections::HashMap};
+use std::fmt::Write;
 
-#[derive(Clone,Copy,Eq,PartialEq,PartialOrd,Hash)]
-struct Point3d(i32,i32,i32);
-
-type Space = HashSet<Point3d>;
+type Space = HashSet<Point3D>;
 
 fn parse_space<P>(filename : P) -> Space
 where P : AsRef<Path> {
@@ -17,7 +15,7 @@ where P : AsRef<Path> {
             if let Ok(point) = line {
                 let parts : Vec<&str> = point.split(',').collect();
                 if parts.len() == 3 {
-                    let p = Point3d(
+                    let p = Point3D::new(
                         parts[0].parse::<i32>().unwrap(),
                         parts[1].parse::<i32>().unwrap(),
                         parts[2].parse::<i32>().unwrap());
@@ -36,14 +34,8 @@ where P : AsRef<Path> {
 fn count_exposed_sides(space : &Space) -> u32 {
     let mut sides = 0;
 
-    let dx : [i32; 6] = [-1, 1, 0,  0, 0,  0];
-    let dy : [i32; 6] = [ 0, 0, 1, -1, 0,  0];
-    let dz : [i32; 6] = [ 0, 0, 0,  0, 1, -1];
-
     for point in space {
-        for i in 0..6 {
-            let adjacent = Point3d(point.0 + dx[i], point.1 + dy[i], point.2 + dz[i]);
-
+        for adjacent in point.orthogonal_neighbors() {
             if !space.contains(&adjacent){
                 sides += 1;
             }
@@ -57,113 +49,219 @@ const AIR : u8 = 0;
 const LAVA : u8 = 1;
 const INNER_AIR : u8 = 2;
 
-fn adjacent_voxels(voxels: &Matrix3D<u8>, x : usize, y : usize, z : usize) -> usize {
-    let mut sides = 0;
+fn bounding_box(space : &Space) -> (Point3D, Point3D) {
+    let mut min = Point3D::new(i32::MAX, i32::MAX, i32::MAX);
+    let mut max = Point3D::new(i32::MIN, i32::MIN, i32::MIN);
 
-    if x == 0 || voxels.element_at(x - 1, y, z).unwrap() == AIR {
-        sides += 1;
+    for p in space {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
     }
 
-    if x == voxels.size_x-1 || voxels.element_at(x + 1, y, z).unwrap() == AIR {
-        sides += 1;
-    }
+    (min, max)
+}
 
-    if y == 0 || voxels.element_at(x, y - 1, z).unwrap() == AIR {
-        sides += 1;
-    }
+// classify every voxel of the bounding box (plus a margin of one voxel) as LAVA,
+// outside AIR (reachable from the margin) or INNER_AIR (trapped inside the droplet)
+fn build_voxels(space : &Space) -> Matrix3D<u8> {
+    let (min, max) = bounding_box(space);
+    let margin = Point3D::new(1, 1, 1);
 
-    if y == voxels.size_y-1 || voxels.element_at(x, y + 1, z).unwrap() == AIR {
-        sides += 1;
-    }
+    let mut voxels : Matrix3D<u8> = Matrix3D::from_bounds(min - margin, max + margin, INNER_AIR);
 
-    if z == 0 || voxels.element_at(x, y, z - 1).unwrap() == AIR {
-        sides += 1;
+    for v in space {
+        voxels.set_at_point(v, LAVA);
     }
 
-    if z == voxels.size_z-1 || voxels.element_at(x, y, z + 1).unwrap() == AIR {
-        sides += 1;
+    // the corner is on the margin, so it's always outside air
+    let corner = voxels.origin;
+    voxels.flood_fill(&corner, AIR);
+
+    voxels
+}
+
+// the side of a voxel that faces along the unit vector `normal`
+#[derive(Clone, Copy)]
+struct Face {
+    voxel : Point3D,
+    normal : Point3D
+}
+
+// the faces of the voxels of the given kind that are adjacent to a different kind of voxel
+fn boundary_faces<F>(voxels : &Matrix3D<u8>, kind : u8, is_across : F) -> Vec<Face>
+where F : Fn(u8) -> bool {
+    let mut faces = Vec::new();
+
+    for (voxel, v) in voxels.voxels() {
+        if v == kind {
+            for n in voxel.orthogonal_neighbors() {
+                if voxels.element_at_point(&n).is_some_and(&is_across) {
+                    faces.push(Face { voxel, normal : n - voxel });
+                }
+            }
+        }
     }
 
-    sides
+    faces
+}
+
+fn exterior_faces(voxels : &Matrix3D<u8>) -> Vec<Face> {
+    boundary_faces(voxels, LAVA, |v| v == AIR)
+}
+
+fn pocket_faces(voxels : &Matrix3D<u8>) -> Vec<Face> {
+    boundary_faces(voxels, INNER_AIR, |v| v != INNER_AIR)
 }
 
 fn count_exterior_surface_area(space : &Space) -> usize {
-    let mut area = 0;
+    if space.is_empty() {
+        return 0;
+    }
 
-    //let minx = space.iter().fold(0i32, |min, &val| if val.0 < min{ val.0 } else{ min });
-    let maxx = space.iter().fold(0i32, |max, &val| if val.0 > max{ val.0 } else{ max });
+    exterior_faces(&build_voxels(space)).len()
+}
+
+// a connected region of air trapped inside the droplet
+struct AirPocket {
+    volume : usize,
+    min : Point3D,
+    max : Point3D,
+    surface_area : usize
+}
+
+struct PocketReport {
+    pockets : Vec<AirPocket>,
+    total_volume : usize,
+    total_surface_area : usize
+}
+
+fn analyze_air_pockets(space : &Space) -> PocketReport {
+    let mut report = PocketReport { pockets : Vec::new(), total_volume : 0, total_surface_area : 0 };
 
-    //let miny = space.iter().fold(0i32, |min, &val| if val.1 < min{ val.1 } else{ min });
-    let maxy = space.iter().fold(0i32, |max, &val| if val.1 > max{ val.1 } else{ max });
+    if space.is_empty() {
+        return report;
+    }
 
-    //let minz = space.iter().fold(0i32, |min, &val| if val.2 < min{ val.2 } else{ min });
-    let maxz = space.iter().fold(0i32, |max, &val| if val.2 > max{ val.2 } else{ max });
+    let voxels = build_voxels(space);
 
-    let umaxx = usize::try_from(maxx).unwrap();
-    let umaxy = usize::try_from(maxy).unwrap();
-    let umaxz = usize::try_from(maxz).unwrap();
+    for component in voxels.connected_components(INNER_AIR) {
+        let (min, max) = bounding_box(&component.iter().copied().collect());
 
-    // build a space of voxels
-    let mut voxels : Matrix3D<u8> = Matrix3D::new(umaxx+1, umaxy+1, umaxz+1, AIR);
+        // a pocket is enclosed by lava, so every side that is not shared with the pocket itself touches lava
+        let surface_area = component.iter()
+            .flat_map(|v| v.orthogonal_neighbors())
+            .filter(|n| voxels.element_at_point(n) != Some(INNER_AIR))
+            .count();
 
-    // set the lava voxels
-    for v in space {
-        voxels.set_at(
-            usize::try_from(v.0).unwrap(), 
-            usize::try_from(v.1).unwrap(), 
-            usize::try_from(v.2).unwrap(), 
-            LAVA);
+        report.total_volume += component.len();
+        report.total_surface_area += surface_area;
+        report.pockets.push(AirPocket { volume : component.len(), min, max, surface_area });
     }
 
-    // set all the voxels that are not on the margin as inner air pockets
-    for x in 1..umaxx {
-        for y in 1..umaxy {
-            for z in 1..umaxz {
-                if let Some(v) = voxels.element_at(x, y, z) {
-                    if v == AIR {
-                        voxels.set_at(x, y, z, INNER_AIR);
-                    }
-                }
-            }
+    report
+}
+
+// split a face of the unit voxel into two triangles, counter-clockwise when seen from outside
+fn face_triangles(face : &Face) -> [[Point3D; 3]; 2] {
+    let axis = (0..3).find(|&i| face.normal[i] != 0).unwrap();
+    let positive = face.normal[axis] > 0;
+
+    // the two axes spanning the face, so that u x v points along +axis
+    let u = (axis + 1) % 3;
+    let v = (axis + 2) % 3;
+
+    let mut corners = [face.voxel; 4];
+    for (i, (du, dv)) in [(0, 0), (1, 0), (1, 1), (0, 1)].into_iter().enumerate() {
+        if positive {
+            corners[i][axis] += 1;
+        }
+        corners[i][u] += du;
+        corners[i][v] += dv;
+    }
+
+    if !positive {
+        corners.reverse();
+    }
+
+    [[corners[0], corners[1], corners[2]], [corners[0], corners[2], corners[3]]]
+}
+
+// an empty droplet gives empty objects
+fn mesh_objects(space : &Space, include_pockets : bool) -> Vec<(&'static str, Vec<Face>)> {
+    if space.is_empty() {
+        let mut objects = vec![("exterior", Vec::new())];
+        if include_pockets {
+            objects.push(("pockets", Vec::new()));
         }
+        return objects;
+    }
+
+    let voxels = build_voxels(space);
+
+    let mut objects = vec![("exterior", exterior_faces(&voxels))];
+    if include_pockets {
+        objects.push(("pockets", pocket_faces(&voxels)));
     }
 
-    // all inner air pockets that are adjacent to outside air must be outside air
-    loop {
-        let mut changed = false;
-
-        for x in 0..=umaxx {
-            for y in 0..=umaxy {
-                for z in 0..=umaxz {
-                    if let Some(e) = voxels.element_at(x, y, z) {
-                        if e == INNER_AIR && adjacent_voxels(&voxels, x, y, z) > 0 {
-                            voxels.set_at(x, y, z, AIR);
-                            changed = true;
-                        }
-                    }
+    objects
+}
+
+// Wavefront OBJ with one object per surface; vertices are shared between triangles
+fn export_obj(space : &Space, include_pockets : bool) -> String {
+    let mut text = String::new();
+    let mut vertices : HashMap<Point3D, usize> = HashMap::new();
+
+    for (name, faces) in mesh_objects(space, include_pockets) {
+        writeln!(text, "o {}", name).unwrap();
+
+        let mut triangles = Vec::new();
+        for face in &faces {
+            for triangle in face_triangles(face) {
+                let mut indices = [0; 3];
+                for (i, vertex) in triangle.iter().enumerate() {
+                    let next = vertices.len() + 1;
+                    indices[i] = *vertices.entry(*vertex).or_insert_with(|| {
+                        writeln!(text, "v {} {} {}", vertex.x(), vertex.y(), vertex.z()).unwrap();
+                        next
+                    });
                 }
+                triangles.push(indices);
             }
         }
 
-        if !changed {
-            break;
+        for [a, b, c] in triangles {
+            writeln!(text, "f {} {} {}", a, b, c).unwrap();
         }
     }
 
-    // count the voxels sides between AIR and LAVA
-    for x in 0..=umaxx {
-        for y in 0..=umaxy {
-            for z in 0..=umaxz {
-                if let Some(v) = voxels.element_at(x, y, z) {
-                    if v == LAVA {
-                        area += adjacent_voxels(&voxels, x, y, z);
-                    }
+    text
+}
+
+// ASCII STL with one solid per surface
+fn export_stl(space : &Space, include_pockets : bool) -> String {
+    let mut text = String::new();
+
+    for (name, faces) in mesh_objects(space, include_pockets) {
+        writeln!(text, "solid {}", name).unwrap();
+
+        for face in &faces {
+            for triangle in face_triangles(face) {
+                writeln!(text, "  facet normal {} {} {}", face.normal.x(), face.normal.y(), face.normal.z()).unwrap();
+                writeln!(text, "    outer loop").unwrap();
+                for vertex in triangle {
+                    writeln!(text, "      vertex {} {} {}", vertex.x(), vertex.y(), vertex.z()).unwrap();
                 }
+                writeln!(text, "    endloop").unwrap();
+                writeln!(text, "  endfacet").unwrap();
             }
         }
+
+        writeln!(text, "endsolid {}", name).unwrap();
     }
 
-    area
+    text
 }
 
 pub fn execute() {
@@ -180,8 +278,47 @@ pub fn execute() {
     let test_area = count_exterior_surface_area(&test_space);
     assert_eq!(58, test_area);
 
+    let offset = Point3D::new(-5, -3, -1);
+    let shifted_space : Space = test_space.iter().map(|&p| p + offset).collect();
+    assert_eq!(58, count_exterior_surface_area(&shifted_space));
+
     let area = count_exterior_surface_area(&space);
     println!("area={}", area);
 
+    let test_report = analyze_air_pockets(&test_space);
+    assert_eq!(1, test_report.pockets.len());
+    assert_eq!(1, test_report.pockets[0].volume);
+    assert_eq!(Point3D::new(2, 2, 5), test_report.pockets[0].min);
+    assert_eq!(Point3D::new(2, 2, 5), test_report.pockets[0].max);
+    assert_eq!(6, test_report.pockets[0].surface_area);
+    assert_eq!(test_count as usize - test_area, test_report.total_surface_area);
+
+    let report = analyze_air_pockets(&space);
+    assert_eq!(count as usize - area, report.total_surface_area);
+    println!("pockets={} volume={}", report.pockets.len(), report.total_volume);
+    for pocket in report.pockets.iter().filter(|p| p.volume > 1) {
+        println!("  volume={} area={} box={}-{}", pocket.volume, pocket.surface_area, pocket.min, pocket.max);
+    }
+
+    // two triangles for every exterior face; the sample has a single pocket of one voxel
+    let test_obj = export_obj(&test_space, false);
+    assert_eq!(2 * 58, test_obj.lines().filter(|l| l.starts_with("f ")).count());
+
+    let test_obj2 = export_obj(&test_space, true);
+    assert_eq!(2 * (58 + 6), test_obj2.lines().filter(|l| l.starts_with("f ")).count());
+    assert_eq!(2, test_obj2.lines().filter(|l| l.starts_with("o ")).count());
+
+    let test_stl = export_stl(&test_space, true);
+    assert_eq!(2 * (58 + 6), test_stl.lines().filter(|l| l.trim_start().starts_with("facet")).count());
+
+    assert_eq!("o exterior\n", export_obj(&Space::new(), false));
+    assert_eq!("solid exterior\nendsolid exterior\nsolid pockets\nendsolid pockets\n", export_stl(&Space::new(), true));
+
+    // the real droplet, to open in a 3D viewer
+    if env::var_os("AOC_EXPORT_DIR").is_some() {
+        utils::export_file("droplet18.obj", export_obj(&space, true));
+        utils::export_file("droplet18.stl", export_stl(&space, true));
+    }
+
     println!();
 }
\ No newline at end of file
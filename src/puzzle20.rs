@@ -5,8 +5,17 @@ pub fn execute() {
     println!("=== puzzle 20 ===");
 
     let test_input = read_numbers("./data/input20test.txt");
+
+    let mut test_mixer = CircularMixer::new(&test_input, 1);
+    test_mixer.mix(1);
+    assert_eq!(vec![1, 2, -3, 4, 0, 3, -2], test_mixer.iter_from(1).unwrap().collect::<Vec<i64>>());
+    assert_eq!(Some(4), test_mixer.after(0, 1000));
+    assert_eq!(Some(4), test_mixer.after(0, -1));
+    assert_eq!(None, test_mixer.after(42, 1));
+
     let test_sum = get_mixed_value(&test_input, 1, 1);
     assert_eq!(3, test_sum);
+    assert_eq!(3, get_mixed_value_naive(&test_input, 1, 1));
 
     let input = read_numbers("./data/input20.txt");
     let sum = get_mixed_value(&input, 1, 1);
@@ -14,6 +23,7 @@ pub fn execute() {
 
     let test_sum2 = get_mixed_value(&test_input, 10, 811589153);
     assert_eq!(1623178306, test_sum2);
+    assert_eq!(1623178306, get_mixed_value_naive(&test_input, 10, 811589153));
 
     let sum2 = get_mixed_value(&input, 10, 811589153);
     println!("{}", sum2);
@@ -30,8 +40,142 @@ where P : AsRef<Path> {
     panic!("Invalid file!");
 }
 
+// an ordering of the elements 0..n, split in blocks of about sqrt(n) elements;
+// finding, removing and inserting an element only touches the block list and one block, so they are O(sqrt(n))
+struct SqrtList {
+    blocks : Vec<Vec<usize>>,
+    // the block each element is in
+    block_of : Vec<usize>,
+    block_size : usize
+}
+
+impl SqrtList {
+    // the list 0, 1, ..., n-1
+    fn new(n : usize) -> SqrtList {
+        let block_size = ((n as f64).sqrt() as usize).max(1);
+        let mut list = SqrtList { blocks : vec![(0..n).collect()], block_of : vec![0; n], block_size };
+        list.rebuild();
+        list
+    }
+
+    fn elements(&self) -> Vec<usize> {
+        self.blocks.iter().flatten().copied().collect()
+    }
+
+    // split the elements in blocks of equal size again
+    fn rebuild(&mut self) {
+        let elements = self.elements();
+        self.blocks = elements.chunks(self.block_size).map(|c| c.to_vec()).collect();
+
+        for (b, block) in self.blocks.iter().enumerate() {
+            for &e in block {
+                self.block_of[e] = b;
+            }
+        }
+    }
+
+    fn position(&self, element : usize) -> usize {
+        let b = self.block_of[element];
+        let before : usize = self.blocks[..b].iter().map(|block| block.len()).sum();
+        before + self.blocks[b].iter().position(|&e| e == element).unwrap()
+    }
+
+    fn get(&self, mut pos : usize) -> usize {
+        for block in &self.blocks {
+            if pos < block.len() {
+                return block[pos];
+            }
+            pos -= block.len();
+        }
+        panic!("Position out of range!");
+    }
+
+    // remove the element and return the position it was at
+    fn remove(&mut self, element : usize) -> usize {
+        let pos = self.position(element);
+        let block = &mut self.blocks[self.block_of[element]];
+        let i = block.iter().position(|&e| e == element).unwrap();
+        block.remove(i);
+        pos
+    }
+
+    fn insert(&mut self, mut pos : usize, element : usize) {
+        for (b, block) in self.blocks.iter_mut().enumerate() {
+            if pos <= block.len() {
+                block.insert(pos, element);
+                self.block_of[element] = b;
+
+                if block.len() > 2 * self.block_size {
+                    self.rebuild();
+                }
+                return;
+            }
+            pos -= block.len();
+        }
+        panic!("Position out of range!");
+    }
+}
+
+// a circular list of numbers (multiplied by the decryption key) that can be mixed any number of rounds;
+// values are looked up by their first occurrence in the original list
+pub struct CircularMixer {
+    values : Vec<i64>,
+    order : SqrtList
+}
+
+impl CircularMixer {
+    pub fn new(values : &[i64], key : i64) -> CircularMixer {
+        CircularMixer { values : values.iter().map(|v| v * key).collect(), order : SqrtList::new(values.len()) }
+    }
+
+    // move every element, in the original order, as many times as the rounds
+    pub fn mix(&mut self, rounds : usize) {
+        let len = self.values.len() as i64;
+        if len < 2 {
+            return;
+        }
+
+        for _ in 0..rounds {
+            for (index, &value) in self.values.iter().enumerate() {
+                let pos = self.order.remove(index);
+                let insertion = (pos as i64 + value).rem_euclid(len - 1) as usize;
+                self.order.insert(insertion, index);
+            }
+        }
+    }
+
+    fn position_of(&self, value : i64) -> Option<usize> {
+        let index = self.values.iter().position(|&v| v == value)?;
+        Some(self.order.position(index))
+    }
+
+    // the current ordering, for one full turn starting at the given value
+    pub fn iter_from(&self, value : i64) -> Option<impl Iterator<Item = i64> + '_> {
+        let start = self.position_of(value)?;
+        let ordering = self.order.elements();
+        let len = ordering.len();
+
+        Some((0..len).map(move |i| self.values[ordering[(start + i) % len]]))
+    }
+
+    // the element k positions after the given value; negative offsets go backwards
+    pub fn after(&self, value : i64, k : i64) -> Option<i64> {
+        let start = self.position_of(value)? as i64;
+        let pos = (start + k).rem_euclid(self.values.len() as i64) as usize;
+        Some(self.values[self.order.get(pos)])
+    }
+}
+
+fn get_mixed_value(input : &[i64], count : usize, key : i64) -> i64 {
+    let mut mixer = CircularMixer::new(input, key);
+    mixer.mix(count);
+
+    // find the elements after specified positions, starting from the value 0
+    [1000, 2000, 3000].iter().map(|&k| mixer.after(0, k).unwrap()).sum()
+}
+
 // solution from https://github.com/AxlLind/AdventOfCode2022/blob/main/src/bin/20.rs
-fn get_mixed_value(input : &Vec<i64>, count : i32, key : i64) ->i64 {
+fn get_mixed_value_naive(input : &[i64], count : i32, key : i64) -> i64 {
     let transformed_input : Vec<i64> = input.iter().map(|e| e * key).collect();
     let mut indexes : Vec<usize> = (0..input.len()).collect();
 
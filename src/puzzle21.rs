@@ -1,34 +1,92 @@
 use crate::utils;
-use std::{path::Path, collections::HashMap, cmp::Ordering};
+use std::{path::Path, collections::HashMap, collections::HashSet, fmt};
+use num::{BigInt, BigRational, Zero, One};
 
 pub fn execute() {
     println!("=== puzzle 21 ===");
 
-    let test_monkeys = read_monkeys("./data/input21test.txt");
-    let test_sum = find_root_value(&test_monkeys, &String::from("root"));
-    assert_eq!(152, test_sum);
+    let test_monkeys = read_monkeys("./data/input21test.txt").unwrap();
+    let test_sum = find_root_value(&test_monkeys, "root");
+    assert_eq!(Ok(152), test_sum);
 
-    let test_monkeys2 = read_monkeys("./data/input21test2.txt");
-    let test_sum2 = find_root_value(&test_monkeys2, &String::from("root"));
+    let test_monkeys2 = read_monkeys("./data/input21test2.txt").unwrap();
+    let test_sum2 = find_root_value(&test_monkeys2, "root").unwrap();
     println!("test sum={}", test_sum2);
 
-    let monkeys = read_monkeys("./data/input21.txt");
-    let sum = find_root_value(&monkeys, &String::from("root"));
+    let monkeys = read_monkeys("./data/input21.txt").unwrap();
+    let sum = find_root_value(&monkeys, "root").unwrap();
     println!("sum={}", sum);
 
-    let test_hum_value = find_humn_value(&test_monkeys, &String::from("root"), &String::from("humn"));
-    assert_eq!(301, test_hum_value);
+    // any names and spacing
+    let group = parse_monkeys("root:left_one+r2\n  left_one :  -3\nr2: 7 \n\n").unwrap();
+    assert_eq!(Ok(4), find_root_value(&group, "root"));
 
-    //let test_hum_value2 = find_humn_value(&test_monkeys2, &String::from("root"), &String::from("humn"));
-    //println!("test human={}", test_hum_value2);
+    assert_eq!(Some(ParseError::InvalidLine(2, "b: c +".to_string())), parse_monkeys("a: 1\nb: c +").err());
+    assert_eq!(Some(ParseError::InvalidCharacter(1, '%')), parse_monkeys("a: b % c").err());
+    assert_eq!(Some(ParseError::DuplicateMonkey(2, "a".to_string())), parse_monkeys("a: 1\na: 2").err());
 
-    let hum_value = find_humn_value(&monkeys, &String::from("root"), &String::from("humn"));
-    println!("human={}", hum_value);
+    let group = parse_monkeys("root: a + b\na: 1").unwrap();
+    assert_eq!(Err(SolveError::UnknownReference("root".to_string(), "b".to_string())), find_root_value(&group, "root"));
+    assert_eq!(Err(SolveError::UnknownMonkey("nope".to_string())), find_root_value(&group, "nope"));
+
+    let group = parse_monkeys("root: a + b\na: b * c\nb: 2\nc: d - e\nd: 5\ne: a / b").unwrap();
+    let cycle = ["a", "c", "e", "a"].iter().map(|n| n.to_string()).collect();
+    assert_eq!(Err(SolveError::Cycle(cycle)), find_root_value(&group, "root"));
+
+    let test_hum_value = find_humn_value(&test_monkeys, "root", "humn");
+    assert_eq!(Ok(301), test_hum_value);
+
+    let test_hum_value2 = find_humn_value(&test_monkeys2, "root", "humn");
+    assert_eq!(Ok(3059361893920), test_hum_value2);
+
+    // humn + humn = 4 has an integer solution, humn + humn = 3 only a rational one
+    let group = parse_monkeys("root: dbl + four\ndbl: humn + humn\nfour: 4\nhumn: 1").unwrap();
+    assert_eq!(Ok(2), find_humn_value(&group, "root", "humn"));
+
+    let group = parse_monkeys("root: dbl + four\ndbl: humn + humn\nfour: 3\nhumn: 1").unwrap();
+    assert_eq!(Err(SolveError::NotInteger(BigRational::new(BigInt::from(3), BigInt::from(2)))), find_humn_value(&group, "root", "humn"));
+
+    let group = parse_monkeys("root: dbl + four\ndbl: humn - humn\nfour: 3\nhumn: 1").unwrap();
+    assert_eq!(Err(SolveError::NoSolution), find_humn_value(&group, "root", "humn"));
+
+    let group = parse_monkeys("root: dbl + four\ndbl: humn - humn\nfour: 0\nhumn: 1").unwrap();
+    assert_eq!(Err(SolveError::InfinitelyManySolutions), find_humn_value(&group, "root", "humn"));
+
+    let group = parse_monkeys("root: dbl + four\ndbl: humn * humn\nfour: 4\nhumn: 1").unwrap();
+    assert_eq!(Err(SolveError::Nonlinear("dbl".to_string())), find_humn_value(&group, "root", "humn"));
+
+    // the human is the unknown whatever the input says it yells, but it must be there
+    let group = parse_monkeys("root: dbl + four\ndbl: humn + humn\nfour: 4\nhumn: four * four").unwrap();
+    assert_eq!(Ok(2), find_humn_value(&group, "root", "humn"));
+    assert_eq!(Err(SolveError::UnknownMonkey("me".to_string())), find_humn_value(&group, "root", "me"));
+
+    let test_equation = Equation::build(&test_monkeys, "root", "humn").unwrap();
+    assert_eq!("(4 + 2 * (humn - 3)) / 4 = (32 - 2) * 5", test_equation.to_string());
+    assert_eq!("(4 + 2 * (humn - 3)) / 4 = 150", test_equation.simplify().to_string());
+    println!("{}", test_equation);
+    println!("{}", test_equation.simplify());
+
+    // parentheses are kept where the operations do not associate
+    let group = parse_monkeys("root: a + b\na: humn - y\ny: c - d\nc: 7\nd: 5\nb: e / f\ne: g * humn\nf: i / j\ng: 6\ni: 9\nj: 2\nhumn: 0").unwrap();
+    let equation = Equation::build(&group, "root", "humn").unwrap();
+    assert_eq!("humn - (7 - 5) = 6 * humn / (9 / 2)", equation.to_string());
+    assert_eq!("humn - 2 = 6 * humn / (9 / 2)", equation.simplify().to_string());
+
+    // a truncating division stays grouped under a product, and an overflowing one is not folded
+    let group = parse_monkeys("root: a + b\na: humn * d\nd: e / f\ne: 7\nf: 2\nb: g / h\ng: x - y\nx: -9223372036854775807\ny: 1\nh: -1\nhumn: 0").unwrap();
+    let equation = Equation::build(&group, "root", "humn").unwrap();
+    assert_eq!("humn * (7 / 2) = (-9223372036854775807 - 1) / -1", equation.to_string());
+    assert_eq!("humn * (7 / 2) = -9223372036854775808 / -1", equation.simplify().to_string());
+
+    match find_humn_value(&monkeys, "root", "humn") {
+        Ok(hum_value) => println!("human={}", hum_value),
+        Err(e) => println!("no human value: {}", e)
+    }
 
     println!();
 }
 
-#[derive(Copy,Clone,PartialEq,Eq)]
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
 enum Operand {
     Plus,
     Minus,
@@ -49,7 +107,6 @@ impl Expression {
 }
 
 struct Monkey {
-    #[allow(unused)]
     name : String,
     value : Option<i64>,
     expr : Option<Expression>
@@ -63,154 +120,440 @@ impl Monkey {
     fn from_expr(n: &str, l : &str, r : &str, o : Operand) -> Monkey {
         Monkey { name: n.to_string(), value: None, expr: Some(Expression::new(l, r, o)) }
     }
+
+    fn dependencies(&self) -> Vec<&str> {
+        match &self.expr {
+            Some(expr) => vec![expr.left.as_str(), expr.right.as_str()],
+            None => Vec::new()
+        }
+    }
 }
 
 type MonkeyGroup = HashMap<String, Monkey>;
 
-fn read_monkeys<P>(filename : P) -> MonkeyGroup
-where P : AsRef<Path> {
-    let mut monkeys = MonkeyGroup::new();
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Name(String),
+    Number(i64),
+    Op(Operand),
+    Colon
+}
 
-    if let Ok(lines) = utils::read_lines(filename) {
-        for line in lines {
-            if let Ok(monkey_line) = line {
-                let name = &monkey_line[0..4];
-                let expr = &monkey_line[6..];
-                if expr.len() > 4 {
-                    let left = &expr[0..4];
-                    let right = &expr[7..];
-                    let c = expr.chars().nth(5).unwrap();
-                    let op = match c {
-                        '+' => Operand::Plus,
-                        '-' => Operand::Minus,
-                        '*' => Operand::Mul,
-                        '/' => Operand::Div,
-                        _ => panic!("Invalid operand")
-                    };
-
-                    monkeys.insert(name.to_string(), Monkey::from_expr(name, left, right, op));
+// errors carry the line number, starting from 1
+#[derive(Clone, PartialEq, Debug)]
+enum ParseError {
+    InvalidCharacter(usize, char),
+    InvalidNumber(usize, String),
+    InvalidLine(usize, String),
+    DuplicateMonkey(usize, String)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidCharacter(line, c) => write!(f, "line {}: invalid character '{}'", line, c),
+            ParseError::InvalidNumber(line, n) => write!(f, "line {}: invalid number '{}'", line, n),
+            ParseError::InvalidLine(line, text) => write!(f, "line {}: expected 'name: number' or 'name: name op name', found '{}'", line, text),
+            ParseError::DuplicateMonkey(line, name) => write!(f, "line {}: monkey '{}' is already defined", line, name)
+        }
+    }
+}
+
+// names are runs of letters, digits and underscores; a run of digits only is a number
+fn tokenize(text : &str, line : usize) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            ':' => Token::Colon,
+            '+' => Token::Op(Operand::Plus),
+            '-' => Token::Op(Operand::Minus),
+            '*' => Token::Op(Operand::Mul),
+            '/' => Token::Op(Operand::Div),
+            c if c.is_whitespace() => continue,
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, n)) = chars.peek() {
+                    if !(n.is_alphanumeric() || n == '_') {
+                        break;
+                    }
+                    end = i + n.len_utf8();
+                    chars.next();
+                }
+
+                let word = &text[start..end];
+                if word.chars().all(|d| d.is_ascii_digit()) {
+                    Token::Number(word.parse::<i64>().map_err(|_| ParseError::InvalidNumber(line, word.to_string()))?)
                 }
                 else {
-                    let value = expr.parse::<i64>().unwrap();
-                    monkeys.insert(name.to_string(), Monkey::from_value(name, value));
+                    Token::Name(word.to_string())
                 }
-            }
-        }
-    }    
+            },
+            c => return Err(ParseError::InvalidCharacter(line, c))
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
 
-    monkeys
+fn parse_monkey(text : &str, line : usize) -> Result<Monkey, ParseError> {
+    match tokenize(text, line)?.as_slice() {
+        [Token::Name(name), Token::Colon, Token::Number(value)] => Ok(Monkey::from_value(name, *value)),
+        [Token::Name(name), Token::Colon, Token::Op(Operand::Minus), Token::Number(value)] => Ok(Monkey::from_value(name, -value)),
+        [Token::Name(name), Token::Colon, Token::Name(left), Token::Op(op), Token::Name(right)] => Ok(Monkey::from_expr(name, left, right, *op)),
+        _ => Err(ParseError::InvalidLine(line, text.trim().to_string()))
+    }
 }
 
-fn find_root_value(monkeys : &MonkeyGroup, name : &String) -> i64 {
-    if let Some(monkey) = monkeys.get(name) {
-        if let Some(value) = monkey.value {
-            return value;
+// one monkey per line, blank lines are skipped
+fn parse_monkeys(text : &str) -> Result<MonkeyGroup, ParseError> {
+    let mut monkeys = MonkeyGroup::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
         }
-        else if let Some(expr) = &monkey.expr {
-            return evaluate(
-                find_root_value(monkeys, &expr.left), 
-                find_root_value(monkeys, &expr.right), 
-                expr.op);
+
+        let monkey = parse_monkey(line, i + 1)?;
+        if monkeys.contains_key(&monkey.name) {
+            return Err(ParseError::DuplicateMonkey(i + 1, monkey.name));
         }
-    } 
+        monkeys.insert(monkey.name.clone(), monkey);
+    }
 
-    0
+    Ok(monkeys)
 }
 
-fn find_humn_value(monkeys : &MonkeyGroup, root_name : &String, humn_name : &String) -> i64 {
-    let root = monkeys.get(root_name).unwrap();
+fn read_monkeys<P>(filename : P) -> Result<MonkeyGroup, ParseError>
+where P : AsRef<Path> {
+    parse_monkeys(&utils::read_file_string(filename).expect("Invalid file!"))
+}
 
-    if let Some(root_expr) = &root.expr {
-        let is_humn_on_left = contains_humn(monkeys, &root_expr.left, &humn_name);
+// the monkeys the given one depends on, each one after the monkeys it listens to;
+// the variable, if any, is a leaf whatever it yells; fails on references to unknown monkeys and on dependency cycles
+fn topological_order<'a>(monkeys : &'a MonkeyGroup, root_name : &'a str, variable : Option<&str>) -> Result<Vec<&'a str>, SolveError> {
+    if !monkeys.contains_key(root_name) {
+        return Err(SolveError::UnknownMonkey(root_name.to_string()));
+    }
 
-        if is_humn_on_left {
-            // find the right tree value
-            let value = find_root_value(&monkeys, &root_expr.right);
+    let mut order = Vec::new();
+    let mut done = HashSet::new();
+    // depth first search: the monkeys being visited and the index of their next dependency
+    let mut path : Vec<(&str, usize)> = vec![(root_name, 0)];
 
-            // continue computing on the left
-            return compute_humn_value(&monkeys, &root_expr.left, &humn_name, value);
-        } 
-        else {
-            // find the left tree value
-            let value = find_root_value(&monkeys, &root_expr.left);
+    while let Some(frame) = path.last_mut() {
+        let name = frame.0;
+        let dependencies = if Some(name) == variable { Vec::new() } else { monkeys[name].dependencies() };
 
-            // continue computing on the right
-            return compute_humn_value(&monkeys, &root_expr.right, &humn_name, value);
-        };
+        if frame.1 == dependencies.len() {
+            path.pop();
+            done.insert(name);
+            order.push(name);
+            continue;
+        }
+
+        let dependency = dependencies[frame.1];
+        frame.1 += 1;
+
+        if done.contains(dependency) {
+            continue;
+        }
+        if let Some(start) = path.iter().position(|&(n, _)| n == dependency) {
+            let mut cycle : Vec<String> = path[start..].iter().map(|&(n, _)| n.to_string()).collect();
+            cycle.push(dependency.to_string());
+            return Err(SolveError::Cycle(cycle));
+        }
+        if !monkeys.contains_key(dependency) {
+            return Err(SolveError::UnknownReference(name.to_string(), dependency.to_string()));
+        }
+
+        path.push((dependency, 0));
     }
 
-    0
+    Ok(order)
 }
 
-fn compute_humn_value(monkeys : &MonkeyGroup, root_name : &String, humn_name : &String, root_value : i64) -> i64 {
-    // if we reached the target node than the root value is the result    
-    if root_name.cmp(humn_name) == Ordering::Equal {
-        return root_value;
+// evaluate the tree below the given monkey bottom-up, with `leaf` giving the value of the monkeys yelling a number
+// (or of the variable, with None) and `combine` the value of the monkeys doing an operation
+fn evaluate_tree<V, L, C>(monkeys : &MonkeyGroup, root_name : &str, variable : Option<&str>, leaf : L, combine : C) -> Result<V, SolveError>
+where L : Fn(Option<i64>) -> V, C : Fn(&V, &V, Operand, &str) -> Result<V, SolveError> {
+    let mut values : HashMap<&str, V> = HashMap::new();
+
+    for name in topological_order(monkeys, root_name, variable)? {
+        let monkey = &monkeys[name];
+        let value = match (&monkey.expr, monkey.value) {
+            _ if Some(name) == variable => leaf(None),
+            (Some(expr), _) => combine(&values[expr.left.as_str()], &values[expr.right.as_str()], expr.op, name)?,
+            (None, Some(value)) => leaf(Some(value)),
+            (None, None) => unreachable!()
+        };
+        values.insert(name, value);
     }
 
-    // get the root node
-    let root = monkeys.get(root_name).unwrap();
+    Ok(values.remove(root_name).unwrap())
+}
 
-    if let Some(root_expr) = &root.expr {
-        // check where the human node is
-        let is_humn_on_left = contains_humn(monkeys, &root_expr.left, &humn_name);                  
+fn find_root_value(monkeys : &MonkeyGroup, name : &str) -> Result<i64, SolveError> {
+    evaluate_tree(monkeys, name, None, |value| value.unwrap(), |&left, &right, op, name| {
+        if op == Operand::Div && right == 0 {
+            return Err(SolveError::DivisionByZero(name.to_string()));
+        }
+        evaluate(left, right, op).ok_or_else(|| SolveError::Overflow(name.to_string()))
+    })
+}
 
-        return if is_humn_on_left {
-            // compute the value of the other sub-tree
-            let other_value = find_root_value(&monkeys, &root_expr.right);
+// a linear function of the human value, a * humn + b, over exact rationals
+#[derive(Clone, PartialEq, Debug)]
+struct Linear {
+    a : BigRational,
+    b : BigRational
+}
 
-            //  compute the value of the sub-tree where the human is
-            let human_tree_value = match root_expr.op {
-                Operand::Plus => root_value - other_value,
-                Operand::Minus => root_value + other_value,
-                Operand::Mul => root_value / other_value,
-                Operand::Div => root_value * other_value            
-            };
+impl Linear {
+    fn constant(v : i64) -> Linear {
+        Linear { a : BigRational::zero(), b : BigRational::from_integer(BigInt::from(v)) }
+    }
+
+    fn variable() -> Linear {
+        Linear { a : BigRational::one(), b : BigRational::zero() }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.a.is_zero()
+    }
+
+    fn scale(&self, k : &BigRational) -> Linear {
+        Linear { a : &self.a * k, b : &self.b * k }
+    }
 
-            // continue recursion with the sub-tree where the human is
-            compute_humn_value(monkeys, &root_expr.left, &humn_name, human_tree_value)
+    // combine two subtrees; fails when the result is not linear in the human value
+    fn apply(&self, other : &Linear, op : Operand, name : &str) -> Result<Linear, SolveError> {
+        match op {
+            Operand::Plus => Ok(Linear { a : &self.a + &other.a, b : &self.b + &other.b }),
+            Operand::Minus => Ok(Linear { a : &self.a - &other.a, b : &self.b - &other.b }),
+            Operand::Mul => {
+                if self.is_constant() {
+                    Ok(other.scale(&self.b))
+                }
+                else if other.is_constant() {
+                    Ok(self.scale(&other.b))
+                }
+                else {
+                    Err(SolveError::Nonlinear(name.to_string()))
+                }
+            },
+            Operand::Div => {
+                if !other.is_constant() {
+                    Err(SolveError::Nonlinear(name.to_string()))
+                }
+                else if other.b.is_zero() {
+                    Err(SolveError::DivisionByZero(name.to_string()))
+                }
+                else {
+                    Ok(self.scale(&other.b.recip()))
+                }
+            }
         }
-        else {
-            let other_value = find_root_value(&monkeys, &root_expr.left);
+    }
+}
 
-            let human_tree_value = match root_expr.op {
-                Operand::Plus => root_value - other_value,
-                Operand::Minus => other_value - root_value,
-                Operand::Mul => root_value / other_value,
-                Operand::Div => other_value / root_value            
-            };
+#[derive(Clone, PartialEq, Debug)]
+enum SolveError {
+    UnknownMonkey(String),
+    // a monkey and the unknown monkey it listens to
+    UnknownReference(String, String),
+    // the monkeys on the cycle, starting and ending with the same one
+    Cycle(Vec<String>),
+    Overflow(String),
+    // the root monkey only yells a number, so there is no equation
+    NoEquation(String),
+    // the monkey multiplies or divides two values that both depend on the human
+    Nonlinear(String),
+    DivisionByZero(String),
+    NoSolution,
+    InfinitelyManySolutions,
+    NotInteger(BigRational),
+    OutOfRange(BigInt)
+}
 
-            compute_humn_value(monkeys, &root_expr.right, &humn_name, human_tree_value)
+impl fmt::Display for SolveError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::UnknownMonkey(name) => write!(f, "unknown monkey '{}'", name),
+            SolveError::UnknownReference(name, other) => write!(f, "monkey '{}' listens to unknown monkey '{}'", name, other),
+            SolveError::Cycle(names) => write!(f, "dependency cycle {}", names.join(" -> ")),
+            SolveError::Overflow(name) => write!(f, "overflow at monkey '{}'", name),
+            SolveError::NoEquation(name) => write!(f, "monkey '{}' has no equation", name),
+            SolveError::Nonlinear(name) => write!(f, "the equation is nonlinear at monkey '{}'", name),
+            SolveError::DivisionByZero(name) => write!(f, "division by zero at monkey '{}'", name),
+            SolveError::NoSolution => write!(f, "the equation has no solution"),
+            SolveError::InfinitelyManySolutions => write!(f, "every value solves the equation"),
+            SolveError::NotInteger(v) => write!(f, "the only solution {} is not an integer", v),
+            SolveError::OutOfRange(v) => write!(f, "the solution {} does not fit in an i64", v)
         }
     }
-    else {
-        panic!("wrong path!");
+}
+
+// the value of the monkey as a function of the human value
+fn linear_form(monkeys : &MonkeyGroup, name : &str, humn_name : &str) -> Result<Linear, SolveError> {
+    evaluate_tree(monkeys, name, Some(humn_name),
+        |value| value.map_or_else(Linear::variable, Linear::constant),
+        |left, right, op, n| left.apply(right, op, n))
+}
+
+// the human value that makes both sides of the root monkey equal
+fn find_humn_value(monkeys : &MonkeyGroup, root_name : &str, humn_name : &str) -> Result<i64, SolveError> {
+    if !monkeys.contains_key(humn_name) {
+        return Err(SolveError::UnknownMonkey(humn_name.to_string()));
     }
+    let root = monkeys.get(root_name).ok_or_else(|| SolveError::UnknownMonkey(root_name.to_string()))?;
+    let root_expr = root.expr.as_ref().ok_or_else(|| SolveError::NoEquation(root_name.to_string()))?;
+
+    // left - right = a * humn + b = 0
+    let left = linear_form(monkeys, &root_expr.left, humn_name)?;
+    let right = linear_form(monkeys, &root_expr.right, humn_name)?;
+    let diff = left.apply(&right, Operand::Minus, root_name)?;
+
+    if diff.a.is_zero() {
+        return Err(if diff.b.is_zero() { SolveError::InfinitelyManySolutions } else { SolveError::NoSolution });
+    }
+
+    let solution = -diff.b / diff.a;
+    if !solution.is_integer() {
+        return Err(SolveError::NotInteger(solution));
+    }
+
+    let value = solution.to_integer();
+    i64::try_from(&value).map_err(|_| SolveError::OutOfRange(value))
+}
+
+// the tree of operations below a monkey, with the human as a variable
+#[derive(Clone, PartialEq, Debug)]
+enum ExprTree {
+    Number(i64),
+    Variable(String),
+    Operation(Box<ExprTree>, Operand, Box<ExprTree>)
 }
 
-fn contains_humn(monkeys : &MonkeyGroup, root_name : &String, humn_name : &String) -> bool {
-    if root_name.cmp(humn_name) == Ordering::Equal {
-        return true;
+impl ExprTree {
+    fn build(monkeys : &MonkeyGroup, name : &str, humn_name : &str) -> Result<ExprTree, SolveError> {
+        evaluate_tree(monkeys, name, Some(humn_name),
+            |value| value.map_or_else(|| ExprTree::Variable(humn_name.to_string()), ExprTree::Number),
+            |left, right, op, _| Ok(ExprTree::Operation(Box::new(left.clone()), op, Box::new(right.clone()))))
     }
 
-    if let Some(monkey) = monkeys.get(root_name) {
-        if let Some(_) = monkey.value {
-            return false;
+    // replace every subtree without the variable by its value; divisions with a remainder are kept
+    fn simplify(&self) -> ExprTree {
+        if let ExprTree::Operation(left, op, right) = self {
+            let left = left.simplify();
+            let right = right.simplify();
+
+            if let (ExprTree::Number(l), ExprTree::Number(r)) = (&left, &right) {
+                let exact = *op != Operand::Div || l.checked_rem(*r) == Some(0);
+                if let (true, Some(value)) = (exact, evaluate(*l, *r, *op)) {
+                    return ExprTree::Number(value);
+                }
+            }
+
+            return ExprTree::Operation(Box::new(left), *op, Box::new(right));
         }
-        else if let Some(expr) = &monkey.expr {
-            return  contains_humn(monkeys, &expr.left, &humn_name) ||
-                    contains_humn(monkeys, &expr.right, &humn_name);
+
+        self.clone()
+    }
+
+    // subtractions and (truncating) divisions do not regroup with the operation on their left
+    fn is_inverse(&self) -> bool {
+        matches!(self, ExprTree::Operation(_, Operand::Minus | Operand::Div, _))
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            ExprTree::Operation(_, Operand::Plus | Operand::Minus, _) => 1,
+            ExprTree::Operation(_, Operand::Mul | Operand::Div, _) => 2,
+            _ => 3
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            Operand::Plus => '+',
+            Operand::Minus => '-',
+            Operand::Mul => '*',
+            Operand::Div => '/'
+        };
+        write!(f, "{}", c)
+    }
+}
+
+// only the parentheses needed to keep the meaning: around lower precedence operands,
+// and around right operands of the same precedence after - and / or when they are - or / themselves
+impl fmt::Display for ExprTree {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprTree::Number(value) => write!(f, "{}", value),
+            ExprTree::Variable(name) => write!(f, "{}", name),
+            ExprTree::Operation(left, op, right) => {
+                let precedence = self.precedence();
+                if left.precedence() < precedence {
+                    write!(f, "({})", left)?;
+                }
+                else {
+                    write!(f, "{}", left)?;
+                }
+
+                write!(f, " {} ", op)?;
+
+                let right_parens = right.precedence() < precedence ||
+                    (right.precedence() == precedence && (matches!(op, Operand::Minus | Operand::Div) || right.is_inverse()));
+                if right_parens {
+                    write!(f, "({})", right)
+                }
+                else {
+                    write!(f, "{}", right)
+                }
+            }
         }
-    } 
+    }
+}
+
+// the equality the root monkey checks
+struct Equation {
+    left : ExprTree,
+    right : ExprTree
+}
 
-    false
+impl Equation {
+    fn build(monkeys : &MonkeyGroup, root_name : &str, humn_name : &str) -> Result<Equation, SolveError> {
+        let root = monkeys.get(root_name).ok_or_else(|| SolveError::UnknownMonkey(root_name.to_string()))?;
+        let root_expr = root.expr.as_ref().ok_or_else(|| SolveError::NoEquation(root_name.to_string()))?;
+
+        Ok(Equation {
+            left : ExprTree::build(monkeys, &root_expr.left, humn_name)?,
+            right : ExprTree::build(monkeys, &root_expr.right, humn_name)?
+        })
+    }
+
+    fn simplify(&self) -> Equation {
+        Equation { left : self.left.simplify(), right : self.right.simplify() }
+    }
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {}", self.left, self.right)
+    }
 }
 
-fn evaluate(left : i64, right : i64, op : Operand) -> i64 {
+// None on overflow or division by zero
+fn evaluate(left : i64, right : i64, op : Operand) -> Option<i64> {
     match op {
-        Operand::Plus => left + right,
-        Operand::Minus => left - right,
-        Operand::Mul => left * right,
-        Operand::Div => left / right
+        Operand::Plus => left.checked_add(right),
+        Operand::Minus => left.checked_sub(right),
+        Operand::Mul => left.checked_mul(right),
+        Operand::Div => left.checked_div(right)
     }
 }
\ No newline at end of file
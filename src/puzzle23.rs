@@ -69,7 +69,7 @@ where P : AsRef<Path> {
 
 fn has_neighbors(grid: &Grid, position : Position) -> bool {
     for i in 0..8 {
-        if let Some(_) = grid.element_at(position.x + DX[i], position.y + DY[i]) {
+        if let Some(_) = grid.element_at(position.x() + DX[i], position.y() + DY[i]) {
             return true;
         }
     }
@@ -82,8 +82,8 @@ fn find_next_position(grid: &Grid, position : Position, round : usize) -> Option
     for _ in 0..4 {
         let mut found = false;
         for i in 0..3 {
-            let x = position.x + DXARR[direction][i];
-            let y = position.y + DYARR[direction][i];
+            let x = position.x() + DXARR[direction][i];
+            let y = position.y() + DYARR[direction][i];
 
             if let Some(_) = grid.element_at(x, y) {
                 found = true;
@@ -92,8 +92,8 @@ fn find_next_position(grid: &Grid, position : Position, round : usize) -> Option
         }
 
         if !found {
-            let newx = position.x + DXMOV[direction];
-            let newy = position.y + DYMOV[direction];
+            let newx = position.x() + DXMOV[direction];
+            let newy = position.y() + DYMOV[direction];
             return Some(Position::new(newx, newy));
         }
 
@@ -144,14 +144,14 @@ fn simulate(grid: &Grid, rounds : usize) -> u32 {
                 if let Some(v) = proposals_count.get(&elf_prop) {
                     // if there is only one proposal then move the elf
                     if *v == 1 {
-                        next_grid.insert(elf_prop.x, elf_prop.y, *elf.1);
+                        next_grid.insert(elf_prop.x(), elf_prop.y(), *elf.1);
                         moved = true;
                     }
                 }
             }
 
             if !moved {
-                next_grid.insert(elf.0.x, elf.0.y, *elf.1);
+                next_grid.insert(elf.0.x(), elf.0.y(), *elf.1);
             }
         }
 
@@ -165,18 +165,18 @@ fn simulate(grid: &Grid, rounds : usize) -> u32 {
     let mut uppery = i32::MIN;
 
     for elf in &crt_grid.points {
-        if lowerx > elf.0.x {
-            lowerx = elf.0.x;
+        if lowerx > elf.0.x() {
+            lowerx = elf.0.x();
         }
-        if upperx < elf.0.x {
-            upperx = elf.0.x;
+        if upperx < elf.0.x() {
+            upperx = elf.0.x();
         }
 
-        if lowery > elf.0.y {
-            lowery = elf.0.y;
+        if lowery > elf.0.y() {
+            lowery = elf.0.y();
         }
-        if uppery < elf.0.y {
-            uppery = elf.0.y;
+        if uppery < elf.0.y() {
+            uppery = elf.0.y();
         }        
     }
 
@@ -236,7 +236,7 @@ fn find_stable_stage(grid: &Grid) -> usize {
                 if let Some(v) = proposals_count.get(&elf_prop) {
                     // if there is only one proposal then move the elf
                     if *v == 1 {
-                        next_grid.insert(elf_prop.x, elf_prop.y, *elf.1);
+                        next_grid.insert(elf_prop.x(), elf_prop.y(), *elf.1);
                         moved = true;
                         changed = true;
                     }
@@ -244,7 +244,7 @@ fn find_stable_stage(grid: &Grid) -> usize {
             }
 
             if !moved {
-                next_grid.insert(elf.0.x, elf.0.y, *elf.1);
+                next_grid.insert(elf.0.x(), elf.0.y(), *elf.1);
             }
         }
 
@@ -1,88 +1,535 @@
 use crate::{utils::{read_lines, as_i32, as_usize}, algebra::{self}};
-use std::{path::Path, collections::HashSet};
+use std::{path::Path, collections::HashMap};
 
 const EMPTY : char        = '.';
 const WIND_UP : char      = '^';
 const WIND_DOWN : char    = 'v';
 const WIND_LEFT : char    = '<';
 const WIND_RIGHT : char   = '>';
+const WALL : char         = '#';
+const EXPEDITION : char   = 'E';
 
 type Map = algebra::Matrix<char>;
 
+type Position = (i32, i32);
+
 pub fn execute() {
     println!("=== puzzle 24 ===");
 
-    let (test_map, test_begin, test_end) = read_map("./data/input24test.txt");
-    let mut test_dist = find_distance(&test_map, test_begin, test_end, 1);
-    assert_eq!(18, test_dist);
+    let test_valley = read_valley("./data/input24test.txt");
+    let test_route = find_route(&test_valley, test_valley.entrance, test_valley.exit, 0).unwrap();
+    assert_eq!(18, test_route.arrival());
+    assert!(is_valid_route(&test_valley, &test_route));
+
+    let test_frames = animate(&test_valley, &test_route);
+    assert_eq!(19, test_frames.len());
+    assert_eq!(concat!(
+        "#E######\n",
+        "#>>.<^<#\n",
+        "#.<..<<#\n",
+        "#>v.><>#\n",
+        "#<^v^^>#\n",
+        "######.#\n"), test_frames[0]);
+    assert_eq!(concat!(
+        "#.######\n",
+        "#E>3.<.#\n",
+        "#<..<<.#\n",
+        "#>2.22.#\n",
+        "#>v..^<#\n",
+        "######.#\n"), render(&test_valley, 1, Some((0, 0))));
+    assert_eq!(concat!(
+        "#.######\n",
+        "#>2.<.<#\n",
+        "#.2v^2<#\n",
+        "#>..>2>#\n",
+        "#<....>#\n",
+        "######E#\n"), test_frames[18]);
+
+    // the blizzard bitsets agree with the map, cell by cell
+    for minute in 0..test_valley.period() {
+        for row in 0..test_valley.height() {
+            let occupied = test_valley.blizzards.occupied(as_usize(row), minute);
+            for col in 0..test_valley.width() {
+                assert_eq!(!test_valley.is_free((row, col), minute), occupied.get(as_usize(col)));
+            }
+        }
+    }
 
-    test_dist = find_distance(&test_map, test_end, test_begin, test_dist);
-    test_dist = find_distance(&test_map, test_begin, test_end, test_dist);
-    assert_eq!(54, test_dist);
+    let mut planner = TourPlanner::new(&test_valley);
+    let (entrance, exit) = (test_valley.entrance, test_valley.exit);
+    let test_tour = planner.tour(&[entrance, exit, entrance, exit], 0).unwrap();
+    assert_eq!(vec![18, 41, 54], test_tour.arrivals);
+    assert_eq!(54, test_tour.total());
+
+    // interior waypoints, and legs starting at the same minute of the blizzard period are only searched once
+    let test_tour = planner.tour(&[entrance, (3, 5), (0, 0), exit], 1).unwrap();
+    assert_eq!(find_distance(&test_valley, (0, 0), exit, test_tour.arrivals[1]), test_tour.arrivals[2]);
+    let waypoints : Vec<Position> = (0..50).map(|i| if i % 2 == 0 { entrance } else { exit }).collect();
+    let test_tour = planner.tour(&waypoints, 0).unwrap();
+    assert_eq!(&[18, 41, 54], &test_tour.arrivals[..3]);
+    assert!(planner.cache.len() < 49);
+    assert_eq!(None, planner.tour(&[entrance, (-1, 3)], 0));
+    assert_eq!(None, planner.tour(&[(-1, 3), exit], 0));
+    assert_eq!(None, planner.tour(&[entrance, (2, 2), (4, 2), exit], 0));
+
+    let valley = read_valley("./data/input24.txt");
+    let route = find_route(&valley, valley.entrance, valley.exit, 0).unwrap();
+    assert!(is_valid_route(&valley, &route));
+    assert_eq!(None, find_route(&valley, (-1, 3), valley.exit, 0));
+    assert_eq!(None, find_route(&valley, valley.entrance, (valley.height(), 0), 0));
+
+    let mut planner = TourPlanner::new(&valley);
+    let tour = planner.tour(&[valley.entrance, valley.exit, valley.entrance, valley.exit], 0).unwrap();
+    println!("dist={}", tour.arrivals[0]);
+    println!("dist 3x={}", tour.total());
 
+    println!();
+}
 
-    let (map, begin, end) = read_map("./data/input24.txt");
-    let mut dist = find_distance(&map, begin, end, 1);
-    println!("dist={}", dist);
+// the inside of the valley without the walls; the entrance is above the first row and the exit below the last one
+struct Valley {
+    map : Map,
+    blizzards : Blizzards,
+    entrance : Position,
+    exit : Position
+}
 
-    dist = find_distance(&map, end, begin, dist);
-    dist = find_distance(&map, begin, end, dist);
+impl Valley {
+    fn height(&self) -> i32 {
+        as_i32(self.map.rows)
+    }
 
-    println!("dist 3x={}", dist);
+    fn width(&self) -> i32 {
+        as_i32(self.map.cols)
+    }
 
-    println!();
+    // the blizzard pattern repeats after this many minutes
+    fn period(&self) -> i32 {
+        num::integer::lcm(self.height(), self.width())
+    }
+
+    fn contains(&self, (row, col) : Position) -> bool {
+        0 <= row && row < self.height() && 0 <= col && col < self.width()
+    }
+
+    // the cells the expedition can ever be in: the inside, the entrance and the exit
+    fn is_walkable(&self, position : Position) -> bool {
+        position == self.entrance || position == self.exit || self.contains(position)
+    }
+
+    // true if the expedition can be in the cell at the given minute
+    fn is_free(&self, position : Position, minute : i32) -> bool {
+        if position == self.entrance || position == self.exit {
+            return true;
+        }
+        if !self.contains(position) {
+            return false;
+        }
+
+        let (row, col) = position;
+        let (height, width) = (self.height(), self.width());
+        let left_cell = self.map.element_at(as_usize(row), as_usize((col - minute).rem_euclid(width))).unwrap();
+        let right_cell = self.map.element_at(as_usize(row), as_usize((col + minute).rem_euclid(width))).unwrap();
+        let top_cell = self.map.element_at(as_usize((row - minute).rem_euclid(height)), as_usize(col)).unwrap();
+        let bottom_cell = self.map.element_at(as_usize((row + minute).rem_euclid(height)), as_usize(col)).unwrap();
+
+        left_cell != WIND_RIGHT && right_cell != WIND_LEFT && top_cell != WIND_DOWN && bottom_cell != WIND_UP
+    }
 }
 
-fn find_distance(map : &Map, begin : (i32,i32), end: (i32, i32), mut step : i32) -> i32 {
-    let (start_row, start_col) = begin;
-    let (end_row, end_col) = end;
+// a row of the valley as a bitset, with the bit c for the column c
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct BitRow {
+    words : Vec<u64>,
+    width : usize
+}
 
-    let mut positions : HashSet<(i32, i32)> = HashSet::new();
+impl BitRow {
+    fn new(width : usize) -> BitRow {
+        BitRow { words : vec![0; width.div_ceil(64)], width }
+    }
 
-    let height = as_i32(map.rows);
-    let width = as_i32(map.cols);
+    fn full(width : usize) -> BitRow {
+        let mut row = BitRow { words : vec![u64::MAX; width.div_ceil(64)], width };
+        row.clear_tail();
+        row
+    }
 
-    'outer : loop {
-        let mut next_positions : HashSet<(i32, i32)> = HashSet::new();
+    // the bits past the width are always 0
+    fn clear_tail(&mut self) {
+        if !self.width.is_multiple_of(64) {
+            let last = self.words.len() - 1;
+            self.words[last] &= (1 << (self.width % 64)) - 1;
+        }
+    }
 
-        for position in &positions {
+    fn get(&self, col : usize) -> bool {
+        col < self.width && self.words[col / 64] & (1 << (col % 64)) != 0
+    }
+
+    fn set(&mut self, col : usize) {
+        self.words[col / 64] |= 1 << (col % 64);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
 
-            let possible_moves = vec![
-                (position.0,    position.1),    // same cell
-                (position.0 - 1,position.1),    // up cell
-                (position.0 + 1,position.1),    // down cell
-                (position.0,    position.1 - 1),// left cell
-                (position.0,    position.1 + 1) // right cell
-            ];
+    // every bit moved n columns to the right, the ones past the width are lost
+    fn shl(&self, n : usize) -> BitRow {
+        let mut row = BitRow::new(self.width);
+        let (words, bits) = (n / 64, n % 64);
 
-            for (row, col) in possible_moves {
-                if (row,col) == (end_row, end_col) {
-                    break 'outer;
+        for i in words..self.words.len() {
+            row.words[i] = self.words[i - words] << bits;
+            if bits > 0 && i > words {
+                row.words[i] |= self.words[i - words - 1] >> (64 - bits);
+            }
+        }
+        row.clear_tail();
+        row
+    }
+
+    // every bit moved n columns to the left, the ones before the first column are lost
+    fn shr(&self, n : usize) -> BitRow {
+        let mut row = BitRow::new(self.width);
+        let (words, bits) = (n / 64, n % 64);
+
+        for i in 0..self.words.len().saturating_sub(words) {
+            row.words[i] = self.words[i + words] >> bits;
+            if bits > 0 && i + words + 1 < self.words.len() {
+                row.words[i] |= self.words[i + words + 1] << (64 - bits);
+            }
+        }
+        row
+    }
+
+    // every bit moved n columns to the right, wrapping around
+    fn rotate(&self, n : usize) -> BitRow {
+        let n = n % self.width;
+        if n == 0 {
+            return self.clone();
+        }
+        self.shl(n).or(&self.shr(self.width - n))
+    }
+
+    fn or(mut self, other : &BitRow) -> BitRow {
+        self.words.iter_mut().zip(&other.words).for_each(|(w, o)| *w |= o);
+        self
+    }
+
+    fn and_not(mut self, other : &BitRow) -> BitRow {
+        self.words.iter_mut().zip(&other.words).for_each(|(w, o)| *w &= !o);
+        self
+    }
+}
+
+// the blizzards of each direction as row bitsets at minute 0;
+// at minute t the horizontal ones are the same rows rotated by t columns,
+// and the vertical ones in a row are the ones that were t rows below (up) or above (down)
+struct Blizzards {
+    up : Vec<BitRow>,
+    down : Vec<BitRow>,
+    left : Vec<BitRow>,
+    right : Vec<BitRow>
+}
+
+impl Blizzards {
+    fn new(map : &Map) -> Blizzards {
+        let empty = vec![BitRow::new(map.cols); map.rows];
+        let mut blizzards = Blizzards { up : empty.clone(), down : empty.clone(), left : empty.clone(), right : empty };
+
+        for row in 0..map.rows {
+            for col in 0..map.cols {
+                match map.element_at(row, col).unwrap() {
+                    WIND_UP => blizzards.up[row].set(col),
+                    WIND_DOWN => blizzards.down[row].set(col),
+                    WIND_LEFT => blizzards.left[row].set(col),
+                    WIND_RIGHT => blizzards.right[row].set(col),
+                    _ => ()
                 }
+            }
+        }
+
+        blizzards
+    }
+
+    // the cells of the row with a blizzard at the given minute
+    fn occupied(&self, row : usize, minute : i32) -> BitRow {
+        let (height, width) = (self.up.len() as i32, self.up[row].width as i32);
+        let r = row as i32;
+
+        self.right[row].rotate(as_usize(minute.rem_euclid(width)))
+            .or(&self.left[row].rotate(as_usize((-minute).rem_euclid(width))))
+            .or(&self.up[as_usize((r + minute).rem_euclid(height))])
+            .or(&self.down[as_usize((r - minute).rem_euclid(height))])
+    }
+}
+
+// the positions the expedition can be in at some minute: one row for the entrance side wall,
+// the rows of the valley, and one row for the exit side wall
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Layer {
+    rows : Vec<BitRow>
+}
+
+impl Layer {
+    fn new(valley : &Valley) -> Layer {
+        Layer { rows : vec![BitRow::new(as_usize(valley.width())); as_usize(valley.height() + 2)] }
+    }
+
+    fn contains(&self, (row, col) : Position) -> bool {
+        row >= -1 && col >= 0 && as_usize(row + 1) < self.rows.len() && self.rows[as_usize(row + 1)].get(as_usize(col))
+    }
+
+    // the position must be in the valley, the entrance or the exit
+    fn insert(&mut self, (row, col) : Position) {
+        self.rows[as_usize(row + 1)].set(as_usize(col));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rows.iter().all(|r| r.is_empty())
+    }
 
-                if 0 <= row && row < height && 0 <= col && col < width {
-                    let left_cell = map.element_at(as_usize(row), as_usize((col - step).rem_euclid(width))).unwrap();
-                    let right_cell = map.element_at(as_usize(row), as_usize((col + step).rem_euclid(width))).unwrap();
-                    let top_cell = map.element_at(as_usize((row - step).rem_euclid(height)), as_usize(col)).unwrap();
-                    let bottom_cell = map.element_at(as_usize((row + step).rem_euclid(height)), as_usize(col)).unwrap();
+    // every position reachable in one minute, without the blizzards of that minute
+    fn expand(&self, valley : &Valley, minute : i32) -> Layer {
+        let width = as_usize(valley.width());
+        let mut next = Layer::new(valley);
 
-                    if left_cell != WIND_RIGHT && right_cell != WIND_LEFT && top_cell != WIND_DOWN && bottom_cell != WIND_UP {
-                        next_positions.insert((row,col));
+        for (i, row) in self.rows.iter().enumerate() {
+            let mut reach = row.clone().or(&row.shl(1)).or(&row.shr(1));
+            if i > 0 {
+                reach = reach.or(&self.rows[i - 1]);
+            }
+            if i + 1 < self.rows.len() {
+                reach = reach.or(&self.rows[i + 1]);
+            }
+
+            // the rows of the walls only have the entrance or the exit open
+            next.rows[i] = if i == 0 || i == self.rows.len() - 1 {
+                let mut open = BitRow::new(width);
+                for (r, c) in [valley.entrance, valley.exit] {
+                    if as_usize(r + 1) == i {
+                        open.set(as_usize(c));
                     }
                 }
+                reach.and_not(&BitRow::full(width).and_not(&open))
             }
+            else {
+                reach.and_not(&valley.blizzards.occupied(i - 1, minute))
+            };
+        }
+
+        next
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Move {
+    Wait,
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+impl Move {
+    const ALL : [Move; 5] = [Move::Wait, Move::Up, Move::Down, Move::Left, Move::Right];
+
+    fn apply(&self, (row, col) : Position) -> Position {
+        match self {
+            Move::Wait => (row, col),
+            Move::Up => (row - 1, col),
+            Move::Down => (row + 1, col),
+            Move::Left => (row, col - 1),
+            Move::Right => (row, col + 1)
+        }
+    }
+
+    fn undo(&self, (row, col) : Position) -> Position {
+        match self {
+            Move::Wait => (row, col),
+            Move::Up => (row + 1, col),
+            Move::Down => (row - 1, col),
+            Move::Left => (row, col + 1),
+            Move::Right => (row, col - 1)
+        }
+    }
+}
+
+// the expedition leaves `begin` at minute `start` and makes one move on every following minute
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Route {
+    begin : Position,
+    start : i32,
+    moves : Vec<Move>
+}
+
+impl Route {
+    fn arrival(&self) -> i32 {
+        self.start + as_i32(self.moves.len())
+    }
+
+    // the position at every minute, from the start to the arrival
+    fn positions(&self) -> Vec<Position> {
+        let mut positions = vec![self.begin];
+        for m in &self.moves {
+            positions.push(m.apply(*positions.last().unwrap()));
         }
+        positions
+    }
+}
 
-        positions = next_positions;
+// the positions reachable at each minute are kept, so the route can be found back from the arrival;
+// the blizzards repeat with the period, so the search gives up when the reachable positions repeat too
+fn find_route(valley : &Valley, begin : Position, end : Position, start : i32) -> Option<Route> {
+    if !valley.is_walkable(begin) || !valley.is_walkable(end) {
+        return None;
+    }
 
-        if positions.is_empty() {
-            positions.insert((start_row, start_col));
+    let period = as_usize(valley.period());
+    let mut first = Layer::new(valley);
+    first.insert(begin);
+    let mut layers = vec![first];
+
+    while !layers.last().unwrap().contains(end) {
+        let minute = start + as_i32(layers.len());
+        let len = layers.len();
+        if layers[len - 1].is_empty() || (len > period && layers[len - 1] == layers[len - 1 - period]) {
+            return None;
         }
 
-        step += 1;
+        let next = layers[len - 1].expand(valley, minute);
+        layers.push(next);
+    }
+
+    // walk back through the layers
+    let mut moves = Vec::new();
+    let mut position = end;
+    for layer in layers.iter().rev().skip(1) {
+        let m = *Move::ALL.iter().find(|m| layer.contains(m.undo(position))).unwrap();
+        position = m.undo(position);
+        moves.push(m);
     }
+    moves.reverse();
+
+    Some(Route { begin, start, moves })
+}
+
+// the minute of arrival at `end` when leaving `begin` at minute `start`
+fn find_distance(valley : &Valley, begin : Position, end : Position, start : i32) -> i32 {
+    find_route(valley, begin, end, start).expect("No route!").arrival()
+}
 
-    step
+// the arrival times at each waypoint after the first one
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Tour {
+    start : i32,
+    arrivals : Vec<i32>
+}
+
+impl Tour {
+    fn total(&self) -> i32 {
+        self.arrivals.last().map_or(0, |a| a - self.start)
+    }
+}
+
+// the time of a leg only depends on the minute it starts in the blizzard period,
+// so the legs are cached by (begin, end, start modulo the period)
+struct TourPlanner<'a> {
+    valley : &'a Valley,
+    cache : HashMap<(Position, Position, i32), Option<i32>>
+}
+
+impl<'a> TourPlanner<'a> {
+    fn new(valley : &'a Valley) -> TourPlanner<'a> {
+        TourPlanner { valley, cache : HashMap::new() }
+    }
+
+    // the number of minutes from `begin` to `end` when leaving at minute `start`
+    fn leg(&mut self, begin : Position, end : Position, start : i32) -> Option<i32> {
+        let key = (begin, end, start.rem_euclid(self.valley.period()));
+        let valley = self.valley;
+        *self.cache.entry(key).or_insert_with(|| find_route(valley, begin, end, start).map(|r| r.arrival() - start))
+    }
+
+    // visit the waypoints in order, leaving the first one at minute `start`
+    fn tour(&mut self, waypoints : &[Position], start : i32) -> Option<Tour> {
+        if !waypoints.iter().all(|&w| self.valley.is_walkable(w)) {
+            return None;
+        }
+
+        let mut arrivals = Vec::new();
+        let mut minute = start;
+
+        for leg in waypoints.windows(2) {
+            minute += self.leg(leg[0], leg[1], minute)?;
+            arrivals.push(minute);
+        }
+
+        Some(Tour { start, arrivals })
+    }
+}
+
+// checked on the map, independently of the blizzard bitsets used by the search
+fn is_valid_route(valley : &Valley, route : &Route) -> bool {
+    route.positions().iter().enumerate().all(|(i, &p)| valley.is_free(p, route.start + as_i32(i)))
+}
+
+// the valley with its walls at the given minute, as in the puzzle text:
+// one blizzard is shown by its direction, more of them by their count
+fn render(valley : &Valley, minute : i32, expedition : Option<Position>) -> String {
+    let (height, width) = (valley.height(), valley.width());
+    let mut blizzards : HashMap<Position, Vec<char>> = HashMap::new();
+
+    for row in 0..height {
+        for col in 0..width {
+            let c = valley.map.element_at(as_usize(row), as_usize(col)).unwrap();
+            let (dr, dc) = match c {
+                WIND_UP => (-1, 0),
+                WIND_DOWN => (1, 0),
+                WIND_LEFT => (0, -1),
+                WIND_RIGHT => (0, 1),
+                _ => continue
+            };
+            let position = ((row + dr * minute).rem_euclid(height), (col + dc * minute).rem_euclid(width));
+            blizzards.entry(position).or_default().push(c);
+        }
+    }
+
+    let mut text = String::new();
+    for row in -1..=height {
+        for col in -1..=width {
+            let position = (row, col);
+            let c = if Some(position) == expedition {
+                EXPEDITION
+            }
+            else if position == valley.entrance || position == valley.exit {
+                EMPTY
+            }
+            else if !valley.contains(position) {
+                WALL
+            }
+            else {
+                match blizzards.get(&position).map(|b| b.as_slice()) {
+                    None => EMPTY,
+                    Some([c]) => *c,
+                    Some(b) => char::from_digit(b.len() as u32, 10).unwrap_or('*')
+                }
+            };
+            text.push(c);
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+// one frame for every minute of the route
+fn animate(valley : &Valley, route : &Route) -> Vec<String> {
+    route.positions().iter().enumerate()
+        .map(|(i, &p)| render(valley, route.start + as_i32(i), Some(p)))
+        .collect()
 }
 
 fn find_entry_exit(map: &Map) -> ((i32, i32),(i32, i32)) {
@@ -109,7 +556,7 @@ fn find_entry_exit(map: &Map) -> ((i32, i32),(i32, i32)) {
     )
 }
 
-fn read_map<P>(filename : P) -> (Map, (i32, i32),(i32, i32)) 
+fn read_valley<P>(filename : P) -> Valley
 where P : AsRef<Path> {
     let mut data : Vec<char> = Vec::new();
     let mut rows = 0;
@@ -138,5 +585,11 @@ where P : AsRef<Path> {
         shrunk_data.extend_from_slice(&map.data[r * cols+1..=(r + 1)* cols - 2]);
     }
 
-    (Map::new_from(rows - 2, cols - 2, shrunk_data), (start_row-1, start_col-1),(end_row-1, end_col-1))
+    let map = Map::new_from(rows - 2, cols - 2, shrunk_data);
+    Valley {
+        blizzards : Blizzards::new(&map),
+        map,
+        entrance : (start_row - 1, start_col - 1),
+        exit : (end_row - 1, end_col - 1)
+    }
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
 use crate::utils::read_lines;
-use std::{path::Path};
+use std::{path::Path, fmt, str::FromStr, iter::Sum};
+use std::ops::{Add, Sub, Mul, Neg};
+use crate::algebra::{BalancedBase, NumeralError, SNAFU, BALANCED_TERNARY};
+use num::BigInt;
 
 pub fn execute() {
     println!("=== puzzle 25 ===");
@@ -7,18 +10,18 @@ pub fn execute() {
     tests();
 
     let test_sum = read_numbers("./data/input25test.txt");
-    assert_eq!(4890, test_sum);
-    let test_snafu = decimal_to_snafu(test_sum);
-    assert_eq!("2=-1=0", test_snafu);
+    assert_eq!(Ok(4890), i64::try_from(&test_sum));
+    assert_eq!("2=-1=0", test_sum.to_string());
 
     let sum = read_numbers("./data/input25.txt");
-    let snafu = decimal_to_snafu(sum);
-    println!("sum(snafu): {}", snafu);
+    println!("sum(snafu): {}", sum);
 
     println!();
 }
 
 fn tests() {
+    let snafu_to_decimal = |text : &str| i64::try_from(&text.parse::<Snafu>().unwrap()).unwrap();
+
     assert_eq!(1, snafu_to_decimal("1"));
     assert_eq!(2, snafu_to_decimal("2"));
     assert_eq!(3, snafu_to_decimal("1="));
@@ -33,67 +36,209 @@ fn tests() {
     assert_eq!(20, snafu_to_decimal("1-0"));
     assert_eq!(2022, snafu_to_decimal("1=11-2"));
     assert_eq!(12345, snafu_to_decimal("1-0---0"));
-    assert_eq!(314159265, snafu_to_decimal("1121-1110-1=0"));    
-}
-
-fn snafu_to_decimal(text : &str) -> i64 {
-    let mut number = 0i64;
-
-    let mut power = 1;
-    for c in text.chars().rev() {
-        match c {
-            '0'..='9' => {
-                number = number + c.to_digit(10).unwrap() as i64 * power;
-            },
-            '-' => {
-                number = number - 1 * power;
-            },
-            '=' => {
-                number = number -2 * power;
-            }
-            _   => {panic!("Invalid character!")}
-        };
-        power = power * 5;
+    assert_eq!(314159265, snafu_to_decimal("1121-1110-1=0"));
+
+    // zero, negative numbers and leading zeros
+    assert_eq!("0", Snafu::from(0).to_string());
+    assert_eq!("-2", Snafu::from(-3).to_string());
+    assert_eq!("-2--1=", Snafu::from(-2022).to_string());
+    assert_eq!(Ok(Snafu::from(3)), "001=".parse());
+    assert_eq!(Ok(Snafu::zero()), "000".parse());
+    for n in -1000..=1000 {
+        assert_eq!(Ok(n), i64::try_from(&Snafu::from(n).to_string().parse::<Snafu>().unwrap()));
     }
 
-    number
+    assert_eq!(Err(SnafuError::Empty), "".parse::<Snafu>());
+    assert_eq!(Err(SnafuError::InvalidDigit(2, '3')), "1-3".parse::<Snafu>());
+
+    // arithmetic on the digits
+    assert_eq!(Snafu::from(2022 + 12345), Snafu::from(2022) + Snafu::from(12345));
+    assert_eq!(Snafu::from(2022 - 12345), Snafu::from(2022) - Snafu::from(12345));
+    assert_eq!(Snafu::from(-2022 * 12345), Snafu::from(2022) * -Snafu::from(12345));
+    assert_eq!(Snafu::from(15), (1..=5).map(Snafu::from).sum());
+
+    assert_eq!(Ok(i128::MAX), i128::try_from(&Snafu::from(i128::MAX)));
+    assert_eq!(Ok(i128::MIN), i128::try_from(&Snafu::from(i128::MIN)));
+    assert_eq!(Err(SnafuError::Overflow), i64::try_from(&(Snafu::from(i64::MAX) + Snafu::from(1))));
+    assert_eq!(Err(SnafuError::Overflow), i128::try_from(&(Snafu::from(i128::MIN) * Snafu::from(2))));
+
+    // other balanced bases, with values of any size
+    assert_eq!("+0-", BALANCED_TERNARY.encode(&BigInt::from(8)));
+    assert_eq!(Ok(BigInt::from(-8)), BALANCED_TERNARY.decode("-0+"));
+
+    let septenary = BalancedBase::new(&['c', 'b', 'a', '0', '1', '2', '3']).unwrap();
+    for n in -1000..=1000 {
+        assert_eq!(Ok(BigInt::from(n)), septenary.decode(&septenary.encode(&BigInt::from(n))));
+    }
+    assert_eq!(Err(NumeralError::InvalidDigit(1, '4')), septenary.decode("14"));
+    assert_eq!(None, BalancedBase::new(&['a', 'b', 'c', 'd']));
+    assert_eq!(None, BalancedBase::new(&['a', 'b', 'a']));
+
+    let big = num::pow(BigInt::from(5), 100) - 1;
+    assert_eq!(format!("1{}-", "0".repeat(99)), SNAFU.encode(&big));
+    assert_eq!(big, BigInt::from(&(Snafu::from(&big) * Snafu::from(1))));
 }
 
-fn decimal_to_snafu(mut number : i64) -> String {    
-    let mut digits : Vec<char> = Vec::new();
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnafuError {
+    Empty,
+    // the position (from the left) and the character
+    InvalidDigit(usize, char),
+    // the number does not fit in the target integer type
+    Overflow
+}
 
-    while number > 0{
-        let r = number % 5;
-        digits.push(match r {
-            0 => '0',
-            1 => '1',
-            2 => '2',
-            3 => '=',
-            4 => '-',
-            _ => unreachable!("Not possible!")
-        });
+impl From<NumeralError> for SnafuError {
+    fn from(e : NumeralError) -> Self {
+        match e {
+            NumeralError::Empty => SnafuError::Empty,
+            NumeralError::InvalidDigit(pos, c) => SnafuError::InvalidDigit(pos, c)
+        }
+    }
+}
 
-        if r >= 3 {
-            number += 5 - r;
+impl fmt::Display for SnafuError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnafuError::Empty => write!(f, "empty SNAFU number"),
+            SnafuError::InvalidDigit(pos, c) => write!(f, "invalid SNAFU digit '{}' at position {}", c, pos),
+            SnafuError::Overflow => write!(f, "SNAFU number out of range")
         }
+    }
+}
 
-        number = number / 5;
+// a number in balanced base 5 with the digits =, -, 0, 1 and 2 (-2 to 2);
+// the digits are kept least significant first, without leading zeros, so zero has no digits
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Snafu {
+    digits : Vec<i64>
+}
+
+impl Snafu {
+    pub fn zero() -> Snafu {
+        Snafu { digits : Vec::new() }
     }
+}
+
+impl FromStr for Snafu {
+    type Err = SnafuError;
 
-    digits.iter().rev().collect::<String>()
+    fn from_str(text : &str) -> Result<Self, Self::Err> {
+        Ok(Snafu { digits : SNAFU.parse_digits(text)? })
+    }
 }
 
-fn read_numbers<P>(filename: P) -> i64 
-where P : AsRef<Path> {
-    let mut sum = 0;
+impl fmt::Display for Snafu {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", SNAFU.format_digits(&self.digits))
+    }
+}
 
-    if let Ok(lines) = read_lines(filename) {
-        for line in lines {
-            if let Ok(number) = line {
-                sum = sum + snafu_to_decimal(&number);
+impl From<&BigInt> for Snafu {
+    fn from(number : &BigInt) -> Self {
+        Snafu { digits : SNAFU.digits_of(number) }
+    }
+}
+
+impl From<i128> for Snafu {
+    fn from(number : i128) -> Self {
+        Snafu::from(&BigInt::from(number))
+    }
+}
+
+impl From<i64> for Snafu {
+    fn from(number : i64) -> Self {
+        Snafu::from(number as i128)
+    }
+}
+
+impl From<i32> for Snafu {
+    fn from(number : i32) -> Self {
+        Snafu::from(number as i128)
+    }
+}
+
+impl From<&Snafu> for BigInt {
+    fn from(snafu : &Snafu) -> Self {
+        SNAFU.value_of(&snafu.digits)
+    }
+}
+
+impl TryFrom<&Snafu> for i128 {
+    type Error = SnafuError;
+
+    fn try_from(snafu : &Snafu) -> Result<Self, Self::Error> {
+        i128::try_from(BigInt::from(snafu)).map_err(|_| SnafuError::Overflow)
+    }
+}
+
+impl TryFrom<&Snafu> for i64 {
+    type Error = SnafuError;
+
+    fn try_from(snafu : &Snafu) -> Result<Self, Self::Error> {
+        i64::try_from(BigInt::from(snafu)).map_err(|_| SnafuError::Overflow)
+    }
+}
+
+// digit-wise addition, the carries never leave balanced base 5
+impl Add for Snafu {
+    type Output = Snafu;
+
+    fn add(self, other : Snafu) -> Snafu {
+        let len = self.digits.len().max(other.digits.len());
+        let values : Vec<i64> = (0..len).map(|i| {
+            self.digits.get(i).unwrap_or(&0) + other.digits.get(i).unwrap_or(&0)
+        }).collect();
+        Snafu { digits : SNAFU.normalize(&values) }
+    }
+}
+
+impl Neg for Snafu {
+    type Output = Snafu;
+
+    fn neg(self) -> Snafu {
+        Snafu { digits : self.digits.iter().map(|d| -d).collect() }
+    }
+}
+
+impl Sub for Snafu {
+    type Output = Snafu;
+
+    fn sub(self, other : Snafu) -> Snafu {
+        self + -other
+    }
+}
+
+// long multiplication on the digits
+impl Mul for Snafu {
+    type Output = Snafu;
+
+    fn mul(self, other : Snafu) -> Snafu {
+        if self.digits.is_empty() || other.digits.is_empty() {
+            return Snafu::zero();
+        }
+
+        let mut values = vec![0i64; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            for (j, &b) in other.digits.iter().enumerate() {
+                values[i + j] += a * b;
             }
         }
+        Snafu { digits : SNAFU.normalize(&values) }
     }
+}
 
-    sum
-}
\ No newline at end of file
+impl Sum for Snafu {
+    fn sum<I : Iterator<Item = Snafu>>(iter : I) -> Snafu {
+        iter.fold(Snafu::zero(), |acc, n| acc + n)
+    }
+}
+
+fn read_numbers<P>(filename: P) -> Snafu
+where P : AsRef<Path> {
+    if let Ok(lines) = read_lines(filename) {
+        return lines.map_while(Result::ok).map(|number| number.parse::<Snafu>().unwrap()).sum();
+    }
+
+    Snafu::zero()
+}
@@ -1,6 +1,9 @@
 use std::io::{self, BufRead};
 use std::fs::{self, File};
+use std::env;
 use std::path::Path;
+use std::hash::Hash;
+use std::collections::HashMap;
 
 #[allow(unused)]
 pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -16,6 +19,18 @@ where P: AsRef<Path> {
     Ok(data)
 }
 
+// the pictures and meshes of some puzzles are only written when AOC_EXPORT_DIR names a directory
+#[allow(unused)]
+pub fn export_file(name : &str, contents : impl AsRef<[u8]>) {
+    if let Some(dir) = env::var_os("AOC_EXPORT_DIR") {
+        let path = Path::new(&dir).join(name);
+        match fs::write(&path, contents) {
+            Ok(_) => println!("exported {}", path.display()),
+            Err(e) => println!("cannot export {}: {}", path.display(), e)
+        }
+    }
+}
+
 #[allow(unused)]
 pub fn as_i32(n : usize) -> i32 {
     i32::try_from(n).unwrap()
@@ -24,4 +39,50 @@ pub fn as_i32(n : usize) -> i32 {
 #[allow(unused)]
 pub fn as_usize(n : i32) -> usize {
     usize::try_from(n).unwrap()
-}
\ No newline at end of file
+}
+
+// a cycle found in a simulation: from step `start` on, the state repeats every `length` steps
+// and the measured quantity grows by `growth` on every repetition; `key` is the repeated state key
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle<K> {
+    pub start : u64,
+    pub length : u64,
+    pub growth : i64,
+    pub key : K
+}
+
+// run `step` on the state until step `target`, or until a state key repeats, and return the measured quantity
+// at `target`; if a cycle is found, the quantity is extrapolated from the steps already simulated
+#[allow(unused)]
+pub fn extrapolate_cycle<S, K, F, G, M>(mut state : S, mut step : F, key : G, measure : M, target : u64) -> (i64, Option<Cycle<K>>)
+where K : Hash + Eq + Clone, F : FnMut(&mut S), G : Fn(&S) -> K, M : Fn(&S) -> i64 {
+    let mut seen : HashMap<K, u64> = HashMap::new();
+    let mut history = vec![measure(&state)];
+    seen.insert(key(&state), 0);
+
+    for n in 1..=target {
+        step(&mut state);
+        history.push(measure(&state));
+
+        let k = key(&state);
+        if let Some(&start) = seen.get(&k) {
+            let length = n - start;
+            let growth = history[as_index(n)] - history[as_index(start)];
+
+            // the state at target is the same as the state at start + offset, but some cycles later
+            let cycles = (target - start) / length;
+            let offset = (target - start) % length;
+            let value = history[as_index(start + offset)] + i64::try_from(cycles).unwrap() * growth;
+
+            return (value, Some(Cycle { start, length, growth, key : k }));
+        }
+        seen.insert(k, n);
+    }
+
+    (history[as_index(target)], None)
+}
+
+fn as_index(n : u64) -> usize {
+    usize::try_from(n).unwrap()
+}